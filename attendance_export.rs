@@ -0,0 +1,672 @@
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use futures::{stream, StreamExt};
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_postgres::{Client, NoTls};
+
+// Office timings used for the late and overtime columns
+const OFFICE_START: (u32, u32) = (9, 30);
+const WORKING_HOURS: f64 = 8.0;
+
+const HEADER: [&str; 17] = [
+    "employee_id",
+    "date",
+    "day",
+    "day_type",
+    "in_time",
+    "out_time",
+    "break_minutes",
+    "lunch_minutes",
+    "worked_hours",
+    "present",
+    "leave",
+    "absent",
+    "holiday",
+    "late",
+    "expected_hours",
+    "overtime_hours",
+    "remarks",
+];
+
+#[derive(Debug, Clone, Serialize)]
+struct AttendanceDay {
+    date: NaiveDate,
+    day_type: String,
+    title: String,
+    in_time: Option<NaiveTime>,
+    out_time: Option<NaiveTime>,
+    break_time: i64,
+    lunch_time: i64,
+    // Approved leave for the day: "full", "first-half" or "second-half"
+    leave_part: Option<String>,
+}
+
+impl AttendanceDay {
+    fn is_present(&self) -> bool {
+        self.day_type == "Present"
+    }
+
+    fn is_holiday(&self) -> bool {
+        self.day_type == "Holiday"
+    }
+
+    /// 1 for a full day of approved leave, 0.5 for a half day
+    fn leave_days(&self) -> f64 {
+        match self.leave_part.as_deref() {
+            _ if self.is_holiday() => 0.0,
+            Some("full") => 1.0,
+            Some(_) => 0.5,
+            None => 0.0,
+        }
+    }
+
+    fn present_days(&self) -> f64 {
+        if self.is_present() {
+            1.0 - self.leave_days()
+        } else {
+            0.0
+        }
+    }
+
+    /// Part of a working day with neither punches nor approved leave
+    fn absent_days(&self) -> f64 {
+        if self.is_present() || self.is_holiday() {
+            0.0
+        } else {
+            1.0 - self.leave_days()
+        }
+    }
+
+    fn is_late(&self) -> bool {
+        // Coming in after a first-half leave is expected
+        if self.leave_part.as_deref() == Some("first-half") {
+            return false;
+        }
+        let office_start = NaiveTime::from_hms_opt(OFFICE_START.0, OFFICE_START.1, 0).unwrap();
+        self.is_present() && self.in_time.is_some_and(|time| time > office_start)
+    }
+
+    /// Half-day leave halves the hours expected for the day
+    fn expected_hours(&self) -> f64 {
+        if self.is_holiday() {
+            0.0
+        } else {
+            WORKING_HOURS * (1.0 - self.leave_days())
+        }
+    }
+
+    /// Hours between in and out punch, minus break and lunch
+    fn worked_hours(&self) -> f64 {
+        match (self.in_time, self.out_time) {
+            (Some(in_time), Some(out_time)) if out_time > in_time => {
+                let seconds = (out_time - in_time).num_seconds() - self.break_time - self.lunch_time;
+                (seconds.max(0) as f64) / 3600.0
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn overtime_hours(&self) -> f64 {
+        if self.is_present() {
+            (self.worked_hours() - self.expected_hours()).max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct AttendanceSummary {
+    present: f64,
+    leave: f64,
+    absent: f64,
+    holiday: u32,
+    late: u32,
+    expected_hours: f64,
+    worked_hours: f64,
+    overtime_hours: f64,
+}
+
+fn summarize(days: &[AttendanceDay]) -> AttendanceSummary {
+    let mut summary = AttendanceSummary::default();
+    for day in days {
+        summary.present += day.present_days();
+        summary.leave += day.leave_days();
+        summary.absent += day.absent_days();
+        summary.holiday += day.is_holiday() as u32;
+        summary.late += day.is_late() as u32;
+        summary.expected_hours += day.expected_hours();
+        summary.worked_hours += day.worked_hours();
+        summary.overtime_hours += day.overtime_hours();
+    }
+    summary
+}
+
+/// Parse "2024-03" into the first and last date of that month
+fn month_range(month: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let start = NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").ok()?;
+    let next_month = if start.month() == 12 {
+        NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1)?
+    };
+    Some((start, next_month.pred_opt()?))
+}
+
+// Employees per round trip when walking a whole team
+const PAGE_SIZE: i64 = 50;
+
+/// Which employees a report covers
+#[derive(Debug, Clone, Copy)]
+enum ReportScope {
+    Employee(i64),
+    Team(i64),
+}
+
+#[derive(Debug, Serialize)]
+struct EmployeeAttendance {
+    employee_id: i64,
+    days: Vec<AttendanceDay>,
+    summary: AttendanceSummary,
+}
+
+#[derive(Debug, Serialize)]
+struct AttendancePage {
+    employees: Vec<EmployeeAttendance>,
+    // Pass as `after` to fetch the next page, None on the last page
+    next_after: Option<i64>,
+}
+
+/// One page of employees with every day of the range, in a single round trip.
+/// The calendar comes from generate_series and the status logs, holidays and
+/// approved leave are aggregated per day and LEFT JOINed onto it.
+async fn load_page(
+    client: &Client,
+    scope: ReportScope,
+    start: NaiveDate,
+    end: NaiveDate,
+    after: i64,
+    limit: i64,
+) -> Result<AttendancePage, tokio_postgres::Error> {
+    let (employee_id, team_id) = match scope {
+        ReportScope::Employee(id) => (Some(id), None),
+        ReportScope::Team(id) => (None, Some(id)),
+    };
+
+    let rows = client
+        .query(
+            "WITH employees AS (
+                SELECT employee_id FROM (
+                    SELECT $1::BIGINT AS employee_id WHERE $1::BIGINT IS NOT NULL
+                    UNION
+                    SELECT employee_id FROM team_member WHERE team_id = $2::BIGINT
+                ) e
+                WHERE employee_id > $5
+                ORDER BY employee_id
+                LIMIT $6
+            ),
+            days AS (
+                SELECT d::DATE AS day
+                FROM generate_series($3::DATE, $4::DATE, INTERVAL '1 day') AS d
+            ),
+            logs AS (
+                SELECT
+                    employee_id,
+                    DATE(created_on) AS day,
+                    MIN(created_on)::TIME AS in_time,
+                    MAX(modified_on)::TIME AS out_time,
+                    CAST(SUM(CASE WHEN status = 'break-in' THEN time_taken ELSE 0 END) AS BIGINT) AS break_time,
+                    CAST(SUM(CASE WHEN status = 'lunch-in' THEN time_taken ELSE 0 END) AS BIGINT) AS lunch_time
+                FROM employee_status_log
+                WHERE employee_id IN (SELECT employee_id FROM employees)
+                    AND DATE(created_on) >= $3
+                    AND DATE(created_on) <= $4
+                GROUP BY employee_id, DATE(created_on)
+            ),
+            holidays AS (
+                SELECT date AS day, MIN(title) AS title
+                FROM holiday
+                WHERE date >= $3 AND date <= $4
+                GROUP BY date
+            ),
+            leaves AS (
+                SELECT lr.employee_id, d::DATE AS day, lr.day_part, lt.title
+                FROM leave_request lr
+                JOIN leave_type lt ON lt.id = lr.leave_type_id
+                CROSS JOIN LATERAL generate_series(
+                    GREATEST(lr.from_date, $3::DATE),
+                    LEAST(lr.to_date, $4::DATE),
+                    INTERVAL '1 day'
+                ) AS d
+                WHERE lr.employee_id IN (SELECT employee_id FROM employees)
+                    AND lr.status = 'approved'
+                    AND lr.from_date <= $4
+                    AND lr.to_date >= $3
+            )
+            SELECT
+                e.employee_id,
+                days.day,
+                l.day IS NOT NULL AS has_log,
+                l.in_time,
+                l.out_time,
+                COALESCE(l.break_time, 0) AS break_time,
+                COALESCE(l.lunch_time, 0) AS lunch_time,
+                EXTRACT(ISODOW FROM days.day) = 7 AS is_sunday,
+                h.title AS holiday_title,
+                lv.day_part,
+                lv.title AS leave_title
+            FROM employees e
+            CROSS JOIN days
+            LEFT JOIN logs l ON l.employee_id = e.employee_id AND l.day = days.day
+            LEFT JOIN holidays h ON h.day = days.day
+            LEFT JOIN leaves lv ON lv.employee_id = e.employee_id AND lv.day = days.day
+            ORDER BY e.employee_id, days.day",
+            &[&employee_id, &team_id, &start, &end, &after, &limit],
+        )
+        .await?;
+
+    let mut employees: Vec<EmployeeAttendance> = vec![];
+    for row in &rows {
+        let employee_id: i64 = row.get("employee_id");
+        let mut item = if row.get("has_log") {
+            AttendanceDay {
+                date: row.get("day"),
+                day_type: "Present".to_string(),
+                title: "".to_string(),
+                in_time: row.get("in_time"),
+                out_time: row.get("out_time"),
+                break_time: row.get("break_time"),
+                lunch_time: row.get("lunch_time"),
+                leave_part: None,
+            }
+        } else {
+            // No punches for this date, absent unless covered by approved leave below
+            AttendanceDay {
+                date: row.get("day"),
+                day_type: "Absent".to_string(),
+                title: "".to_string(),
+                in_time: None,
+                out_time: None,
+                break_time: 0,
+                lunch_time: 0,
+                leave_part: None,
+            }
+        };
+
+        if let Some(day_part) = row.get::<_, Option<String>>("day_part") {
+            let leave_title: String = row.get("leave_title");
+            if day_part == "full" {
+                item.day_type = "Leave".to_string();
+                item.title = leave_title;
+            } else {
+                if !item.is_present() {
+                    item.day_type = "Leave".to_string();
+                }
+                item.title = format!("{} ({})", leave_title, day_part);
+            }
+            item.leave_part = Some(day_part);
+        }
+
+        // Sundays and company holidays override whatever was logged
+        if row.get("is_sunday") {
+            item.day_type = "Holiday".to_string();
+            item.title = "Weekend - Sunday".to_string();
+        } else if let Some(title) = row.get::<_, Option<String>>("holiday_title") {
+            item.day_type = "Holiday".to_string();
+            item.title = title;
+        }
+
+        // Rows are ordered by employee, so a new id starts a new group
+        match employees.last_mut() {
+            Some(last) if last.employee_id == employee_id => last.days.push(item),
+            _ => employees.push(EmployeeAttendance {
+                employee_id,
+                days: vec![item],
+                summary: AttendanceSummary::default(),
+            }),
+        }
+    }
+
+    for employee in employees.iter_mut() {
+        employee.summary = summarize(&employee.days);
+    }
+
+    let next_after = if employees.len() as i64 == limit {
+        employees.last().map(|e| e.employee_id)
+    } else {
+        None
+    };
+
+    Ok(AttendancePage {
+        employees,
+        next_after,
+    })
+}
+
+fn format_time(time: Option<NaiveTime>) -> String {
+    time.map(|t| t.format("%H:%M").to_string()).unwrap_or_default()
+}
+
+/// CSV rows for one employee, the last row holds the month totals
+fn write_csv(employee_id: i64, days: &[AttendanceDay]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    for day in days {
+        writer.write_record([
+            employee_id.to_string(),
+            day.date.format("%Y-%m-%d").to_string(),
+            day.date.format("%a").to_string(),
+            day.day_type.clone(),
+            format_time(day.in_time),
+            format_time(day.out_time),
+            (day.break_time / 60).to_string(),
+            (day.lunch_time / 60).to_string(),
+            format!("{:.2}", day.worked_hours()),
+            day.present_days().to_string(),
+            day.leave_days().to_string(),
+            day.absent_days().to_string(),
+            (day.is_holiday() as u32).to_string(),
+            (day.is_late() as u32).to_string(),
+            format!("{:.2}", day.expected_hours()),
+            format!("{:.2}", day.overtime_hours()),
+            day.title.clone(),
+        ])?;
+    }
+
+    let summary = summarize(days);
+    writer.write_record([
+        employee_id.to_string(),
+        "Total".to_string(),
+        "".to_string(),
+        "".to_string(),
+        "".to_string(),
+        "".to_string(),
+        "".to_string(),
+        "".to_string(),
+        format!("{:.2}", summary.worked_hours),
+        summary.present.to_string(),
+        summary.leave.to_string(),
+        summary.absent.to_string(),
+        summary.holiday.to_string(),
+        summary.late.to_string(),
+        format!("{:.2}", summary.expected_hours),
+        format!("{:.2}", summary.overtime_hours),
+        "".to_string(),
+    ])?;
+
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// One worksheet per employee
+fn write_xlsx(
+    month: &str,
+    employees: &[(i64, Vec<AttendanceDay>)],
+) -> Result<Vec<u8>, rust_xlsxwriter::XlsxError> {
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let hours = Format::new().set_num_format("0.00");
+
+    for (employee_id, days) in employees {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(format!("{} {}", employee_id, month))?;
+
+        for (col, title) in HEADER.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *title, &bold)?;
+        }
+
+        let mut row = 1;
+        for day in days {
+            sheet.write_number(row, 0, *employee_id as f64)?;
+            sheet.write_string(row, 1, day.date.format("%Y-%m-%d").to_string())?;
+            sheet.write_string(row, 2, day.date.format("%a").to_string())?;
+            sheet.write_string(row, 3, &day.day_type)?;
+            sheet.write_string(row, 4, format_time(day.in_time))?;
+            sheet.write_string(row, 5, format_time(day.out_time))?;
+            sheet.write_number(row, 6, (day.break_time / 60) as f64)?;
+            sheet.write_number(row, 7, (day.lunch_time / 60) as f64)?;
+            sheet.write_number_with_format(row, 8, day.worked_hours(), &hours)?;
+            sheet.write_number(row, 9, day.present_days())?;
+            sheet.write_number(row, 10, day.leave_days())?;
+            sheet.write_number(row, 11, day.absent_days())?;
+            sheet.write_number(row, 12, day.is_holiday() as u32)?;
+            sheet.write_number(row, 13, day.is_late() as u32)?;
+            sheet.write_number_with_format(row, 14, day.expected_hours(), &hours)?;
+            sheet.write_number_with_format(row, 15, day.overtime_hours(), &hours)?;
+            sheet.write_string(row, 16, &day.title)?;
+            row += 1;
+        }
+
+        let summary = summarize(days);
+        sheet.write_string_with_format(row, 1, "Total", &bold)?;
+        sheet.write_number_with_format(row, 8, summary.worked_hours, &hours)?;
+        sheet.write_number_with_format(row, 9, summary.present, &bold)?;
+        sheet.write_number_with_format(row, 10, summary.leave, &bold)?;
+        sheet.write_number_with_format(row, 11, summary.absent, &bold)?;
+        sheet.write_number_with_format(row, 12, summary.holiday, &bold)?;
+        sheet.write_number_with_format(row, 13, summary.late, &bold)?;
+        sheet.write_number_with_format(row, 14, summary.expected_hours, &hours)?;
+        sheet.write_number_with_format(row, 15, summary.overtime_hours, &hours)?;
+
+        sheet.autofit();
+    }
+
+    workbook.save_to_buffer()
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    month: String,
+    employee_id: Option<i64>,
+    team_id: Option<i64>,
+    format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportQuery {
+    month: String,
+    employee_id: Option<i64>,
+    team_id: Option<i64>,
+    after: Option<i64>,
+    limit: Option<i64>,
+}
+
+type AppState = Arc<Client>;
+
+fn get_scope(employee_id: Option<i64>, team_id: Option<i64>) -> Result<ReportScope, (StatusCode, String)> {
+    match (employee_id, team_id) {
+        (Some(employee_id), _) => Ok(ReportScope::Employee(employee_id)),
+        (None, Some(team_id)) => Ok(ReportScope::Team(team_id)),
+        (None, None) => Err((
+            StatusCode::BAD_REQUEST,
+            "employee_id or team_id is required".to_string(),
+        )),
+    }
+}
+
+fn internal_error<E: std::fmt::Display>(e: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/attendance/report",
+    summary = "Monthly attendance for an employee or a page of team members",
+    params(
+        ("month" = String, Query, description = "Month as YYYY-MM"),
+        ("employee_id" = Option<i64>, Query, description = "Single employee"),
+        ("team_id" = Option<i64>, Query, description = "Every member of the team"),
+        ("after" = Option<i64>, Query, description = "next_after from the previous page"),
+        ("limit" = Option<i64>, Query, description = "Employees per page, max 50"),
+    ),
+    responses(
+        (status = 200, description = "Attendance page"),
+        (status = 400, description = "Invalid month or missing employee/team"),
+    )
+)]
+async fn get_attendance_report(
+    State(client): State<AppState>,
+    Query(input): Query<ReportQuery>,
+) -> Result<Json<AttendancePage>, (StatusCode, String)> {
+    let (start, end) = month_range(&input.month)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid month, expected YYYY-MM".to_string()))?;
+    let scope = get_scope(input.employee_id, input.team_id)?;
+    let limit = input.limit.unwrap_or(PAGE_SIZE).clamp(1, PAGE_SIZE);
+
+    let page = load_page(&client, scope, start, end, input.after.unwrap_or(0), limit)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(page))
+}
+
+#[utoipa::path(
+    get,
+    path = "/attendance/export",
+    summary = "Download the monthly attendance sheet",
+    description = "Returns one row per day with present, leave, absent, holiday, late and overtime totals as CSV or XLSX",
+    params(
+        ("month" = String, Query, description = "Month as YYYY-MM"),
+        ("employee_id" = Option<i64>, Query, description = "Single employee"),
+        ("team_id" = Option<i64>, Query, description = "Every member of the team"),
+        ("format" = Option<String>, Query, description = "csv (default) or xlsx"),
+    ),
+    responses(
+        (status = 200, description = "File downloaded successfully", content_type = "text/csv"),
+        (status = 400, description = "Invalid month or missing employee/team"),
+    )
+)]
+async fn export_attendance(
+    State(client): State<AppState>,
+    Query(input): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let (start, end) = month_range(&input.month)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid month, expected YYYY-MM".to_string()))?;
+    let scope = get_scope(input.employee_id, input.team_id)?;
+
+    let scope_name = match scope {
+        ReportScope::Employee(employee_id) => format!("employee-{}", employee_id),
+        ReportScope::Team(team_id) => format!("team-{}", team_id),
+    };
+
+    match input.format.as_deref().unwrap_or("csv") {
+        "csv" => {
+            let filename = format!("attendance-{}-{}.csv", input.month, scope_name);
+
+            // Header first, then one chunk per page of employees so large teams are never held in memory
+            let header = Bytes::from(format!("{}\n", HEADER.join(",")));
+            let body = stream::once(async move { Ok::<_, std::io::Error>(header) }).chain(
+                stream::unfold(
+                    (client, Some(0)),
+                    move |(client, after)| async move {
+                        let after = after?;
+                        let page = match load_page(&client, scope, start, end, after, PAGE_SIZE).await {
+                            Ok(page) => page,
+                            Err(e) => return Some((Err(std::io::Error::other(e)), (client, None))),
+                        };
+
+                        let mut chunk = vec![];
+                        for employee in &page.employees {
+                            match write_csv(employee.employee_id, &employee.days) {
+                                Ok(bytes) => chunk.extend(bytes),
+                                Err(e) => return Some((Err(std::io::Error::other(e)), (client, None))),
+                            }
+                        }
+                        Some((Ok(Bytes::from(chunk)), (client, page.next_after)))
+                    },
+                ),
+            );
+
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "text/csv")
+                .header(
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", filename),
+                )
+                .body(StreamBody::new(body))
+                .map_err(internal_error)?
+                .into_response())
+        }
+        "xlsx" => {
+            let filename = format!("attendance-{}-{}.xlsx", input.month, scope_name);
+
+            let mut employees = vec![];
+            let mut after = Some(0);
+            while let Some(cursor) = after {
+                let page = load_page(&client, scope, start, end, cursor, PAGE_SIZE)
+                    .await
+                    .map_err(internal_error)?;
+                after = page.next_after;
+                employees.extend(page.employees.into_iter().map(|e| (e.employee_id, e.days)));
+            }
+            let contents = write_xlsx(&input.month, &employees).map_err(internal_error)?;
+
+            Ok((
+                StatusCode::OK,
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet".to_string(),
+                    ),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", filename),
+                    ),
+                ],
+                contents,
+            )
+                .into_response())
+        }
+        _ => Err((StatusCode::BAD_REQUEST, "format must be csv or xlsx".to_string())),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), tokio_postgres::Error> {
+    let (client, connection) = tokio_postgres::connect(
+        "host=localhost user=postgres dbname=postgres password=password",
+        NoTls,
+    )
+    .await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+
+    let app = Router::new()
+        .route("/attendance/report", get(get_attendance_report))
+        .route("/attendance/export", get(export_attendance))
+        .with_state(Arc::new(client));
+
+    println!("🚀 Server running at http://127.0.0.1:3000/attendance/export?month=2024-03&employee_id=1001");
+    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+
+    Ok(())
+}
+
+
+[dependencies]
+axum = "0.6"
+tokio = { version = "1", features = ["full"] }
+tokio-postgres = { version = "0.7", features = ["with-chrono-0_4"] }
+chrono = "0.4"
+serde = { version = "1", features = ["derive"] }
+futures = "0.3"
+csv = "1"
+rust_xlsxwriter = "0.79"
+utoipa = "5"
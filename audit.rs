@@ -0,0 +1,252 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio_postgres::types::ToSql;
+
+const AUDIT_PAGE_SIZE: i64 = 50;
+
+// Columns that change on every write and would only add noise to a diff
+const AUDIT_IGNORED_FIELDS: [&str; 1] = ["modified_on"];
+
+/// Who changed what, passed to UpdateBuilder::audit
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub org_id: String,
+    pub actor_id: String,
+    // "category", "product"
+    pub entity: &'static str,
+    pub entity_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_on: NaiveDateTime,
+    pub actor_id: String,
+    pub action: String,
+    pub diff: Value,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditHistory {
+    pub entries: Vec<AuditEntry>,
+    // Pass as `before_id` to get the next (older) page
+    pub next_before_id: Option<i64>,
+}
+
+/// Changed fields between two row snapshots as {"path": {"old": .., "new": ..}}.
+/// Nested objects are walked so a doc change shows as "doc.icon" rather than
+/// the whole doc, arrays are compared as a whole.
+pub fn json_diff(before: &Value, after: &Value) -> Map<String, Value> {
+    let mut diff = Map::new();
+    diff_into("", before, after, &mut diff);
+    diff
+}
+
+fn diff_into(path: &str, before: &Value, after: &Value, diff: &mut Map<String, Value>) {
+    match (before, after) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                if path.is_empty() && AUDIT_IGNORED_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let child = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_into(
+                    &child,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    diff,
+                );
+            }
+        }
+        _ if before != after => {
+            let mut change = Map::new();
+            change.insert("old".to_string(), before.clone());
+            change.insert("new".to_string(), after.clone());
+            diff.insert(path.to_string(), Value::Object(change));
+        }
+        _ => {}
+    }
+}
+
+/// Whole row as JSON, locked until the transaction ends so the audit
+/// snapshot matches what the update overwrites. Must run in the transaction
+/// of that update, FOR UPDATE outside of one would release the lock at once.
+pub async fn snapshot_row(
+    db: &DBConnection<'_>,
+    table: &'static str,
+    filters: &[(&'static str, &(dyn ToSql + Sync))],
+    lock: bool,
+) -> ApiResult<Option<Value>> {
+    let where_clauses: Vec<String> = filters
+        .iter()
+        .enumerate()
+        .map(|(i, (column, _))| format!("t.{} = ${}", column, i + 1))
+        .collect();
+    let params: Vec<&(dyn ToSql + Sync)> = filters.iter().map(|(_, value)| *value).collect();
+
+    let query = format!(
+        "SELECT to_jsonb(t) AS snapshot FROM {} t WHERE {}{}",
+        table,
+        where_clauses.join(" AND "),
+        if lock { " FOR UPDATE" } else { "" }
+    );
+    Ok(db
+        .query_opt(&query, &params)
+        .await?
+        .map(|row| row.get("snapshot")))
+}
+
+/// Store an audit row, nothing is stored when no field changed
+pub async fn record_audit(
+    db: &DBConnection<'_>,
+    context: &AuditContext,
+    action: &str,
+    before: &Value,
+    after: &Value,
+) -> ApiResult<Option<i64>> {
+    let diff = json_diff(before, after);
+    if diff.is_empty() {
+        return Ok(None);
+    }
+
+    let row = db
+        .query_one(
+            "INSERT INTO doc_audit (org_id, entity, entity_id, actor_id, action, diff, before, after)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id",
+            &[
+                &context.org_id,
+                &context.entity,
+                &context.entity_id,
+                &context.actor_id,
+                &action,
+                &Value::Object(diff),
+                before,
+                after,
+            ],
+        )
+        .await?;
+    Ok(Some(row.get("id")))
+}
+
+/// Changes of one entity, newest first
+pub async fn get_audit_history(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    entity: &str,
+    entity_id: &str,
+    before_id: Option<i64>,
+    limit: Option<i64>,
+) -> ApiResult<AuditHistory> {
+    let limit = limit.unwrap_or(AUDIT_PAGE_SIZE).clamp(1, AUDIT_PAGE_SIZE);
+    let fetch = limit + 1;
+
+    let rows = db
+        .query(
+            "SELECT id, created_on, actor_id, action, diff
+            FROM doc_audit
+            WHERE org_id = $1 AND entity = $2 AND entity_id = $3
+              AND ($4::BIGINT IS NULL OR id < $4)
+            ORDER BY id DESC
+            LIMIT $5",
+            &[&org_id, &entity, &entity_id, &before_id, &fetch],
+        )
+        .await?;
+
+    let mut entries: Vec<AuditEntry> = rows
+        .iter()
+        .map(|row| AuditEntry {
+            id: row.get("id"),
+            created_on: row.get("created_on"),
+            actor_id: row.get_as_string("actor_id"),
+            action: row.get_as_string("action"),
+            diff: row.get("diff"),
+        })
+        .collect();
+
+    let next_before_id = if entries.len() as i64 > limit {
+        entries.truncate(limit as usize);
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+
+    Ok(AuditHistory {
+        entries,
+        next_before_id,
+    })
+}
+
+/// Put the entity back to how it was right after audit entry `audit_id`.
+/// Only `columns` are written back, modified_on is set to now either way. The
+/// restore itself is audited as 'restore'.
+pub async fn restore_audit_version(
+    db: &DBConnection<'_>,
+    context: &AuditContext,
+    table: &'static str,
+    columns: &[&'static str],
+    filters: &[(&'static str, &(dyn ToSql + Sync))],
+    audit_id: i64,
+) -> ApiResult<u64> {
+    // Entities are named after their table, another entity's version would
+    // be written into the wrong table
+    if context.entity != table {
+        return Err(ApiError::Error(format!(
+            "Audit entity {} does not match table {}",
+            context.entity, table
+        )));
+    }
+    let row = db
+        .query_opt(
+            "SELECT after FROM doc_audit
+            WHERE id = $1 AND org_id = $2 AND entity = $3 AND entity_id = $4",
+            &[&audit_id, &context.org_id, &table, &context.entity_id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::Error("Invalid audit id".to_string()))?;
+    let version: Value = row.get("after");
+
+    let before = match snapshot_row(db, table, filters, true).await? {
+        Some(before) => before,
+        None => return Ok(0),
+    };
+
+    // jsonb_populate_record turns the snapshot back into typed columns.
+    // modified_on is always set to now, a second assignment would be an error.
+    let mut set_clauses: Vec<String> = columns
+        .iter()
+        .filter(|column| **column != "modified_on")
+        .map(|column| format!("{} = r.{}", column, column))
+        .collect();
+    set_clauses.push("modified_on = current_timestamp".to_string());
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![&version];
+    let mut where_clauses = Vec::new();
+    for (column, value) in filters {
+        params.push(*value);
+        where_clauses.push(format!("t.{} = ${}", column, params.len()));
+    }
+
+    let query = format!(
+        "UPDATE {table} t SET {}
+        FROM jsonb_populate_record(NULL::{table}, $1) r
+        WHERE {}",
+        set_clauses.join(", "),
+        where_clauses.join(" AND ")
+    );
+    let affected = db.execute(&query, &params).await?;
+
+    if affected != 0 {
+        if let Some(after) = snapshot_row(db, table, filters, false).await? {
+            record_audit(db, context, "restore", &before, &after).await?;
+        }
+    }
+    Ok(affected)
+}
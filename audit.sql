@@ -0,0 +1,19 @@
+-- One row per change made through UpdateBuilder (update_builder.rs) with an audit context.
+-- before/after are full row snapshots (to_jsonb), diff only holds the changed fields
+-- as {"path": {"old": ..., "new": ...}} with doc fields flattened as "doc.<field>".
+-- action: 'update' or 'restore'
+CREATE TABLE IF NOT EXISTS doc_audit (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    created_on TIMESTAMP DEFAULT now(),
+    org_id VARCHAR(24) NOT NULL,
+    entity VARCHAR(32) NOT NULL,
+    entity_id VARCHAR(24) NOT NULL,
+    actor_id VARCHAR(24) NOT NULL,
+    action VARCHAR(16) NOT NULL DEFAULT 'update',
+    diff JSONB NOT NULL,
+    before JSONB NOT NULL,
+    after JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS doc_audit_entity
+    ON doc_audit (org_id, entity, entity_id, id DESC);
@@ -4,6 +4,35 @@ pub async fn get_all_child_ids(
     parent_ids: &Vec<String>,
 ) -> ApiResult<Vec<String>> {
 
+    // Closure table lookup, skipping anything below a deleted category
+    if has_category_closure(db).await? {
+        let rows = db
+            .query(
+                r#"
+                SELECT DISTINCT cc.descendant_id AS doc_id
+                FROM category_closure cc
+                JOIN category c ON c.doc_id = cc.descendant_id
+                WHERE cc.ancestor_id = ANY($1)
+                  AND cc.depth > 0
+                  AND c.org_id = $2
+                  AND c.is_deleted = false
+                  AND NOT EXISTS (
+                      SELECT 1
+                      FROM category_closure x
+                      JOIN category xa ON xa.doc_id = x.ancestor_id
+                      WHERE x.descendant_id = cc.descendant_id
+                        AND x.depth < cc.depth
+                        AND x.depth > 0
+                        AND xa.is_deleted = true
+                  )
+                "#,
+                &[parent_ids, &org_id],
+            )
+            .await?;
+
+        return Ok(rows.into_iter().map(|r| r.get("doc_id")).collect());
+    }
+
     let rows = db
         .query(
             r#"
@@ -0,0 +1,91 @@
+-- Optional closure table for the category hierarchy: one row per (ancestor, descendant)
+-- pair including each category with itself at depth 0. Kept current by the triggers
+-- below, category_tree.rs, cat_chil.rs and the product filters (product_filter.rs) use
+-- it instead of recursive CTEs when the table exists.
+-- Soft deleted categories keep their rows, queries filter them out through category.
+CREATE TABLE IF NOT EXISTS category_closure (
+    org_id VARCHAR(24) NOT NULL,
+    ancestor_id VARCHAR(24) NOT NULL,
+    descendant_id VARCHAR(24) NOT NULL,
+    depth INT NOT NULL,
+    PRIMARY KEY (ancestor_id, descendant_id)
+);
+
+CREATE INDEX IF NOT EXISTS category_closure_descendant
+    ON category_closure (descendant_id, depth);
+CREATE INDEX IF NOT EXISTS category_closure_org
+    ON category_closure (org_id);
+
+
+CREATE OR REPLACE FUNCTION category_closure_insert() RETURNS trigger AS $$
+BEGIN
+    INSERT INTO category_closure (org_id, ancestor_id, descendant_id, depth)
+    SELECT NEW.org_id, NEW.doc_id, NEW.doc_id, 0
+    UNION ALL
+    SELECT NEW.org_id, ancestor_id, NEW.doc_id, depth + 1
+    FROM category_closure
+    WHERE descendant_id = NEW.parent_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Moving a category detaches its whole subtree from the old ancestors
+-- and attaches it below every ancestor of the new parent
+CREATE OR REPLACE FUNCTION category_closure_move() RETURNS trigger AS $$
+BEGIN
+    DELETE FROM category_closure
+    WHERE descendant_id IN (SELECT descendant_id FROM category_closure WHERE ancestor_id = NEW.doc_id)
+      AND ancestor_id NOT IN (SELECT descendant_id FROM category_closure WHERE ancestor_id = NEW.doc_id);
+
+    INSERT INTO category_closure (org_id, ancestor_id, descendant_id, depth)
+    SELECT NEW.org_id, parent.ancestor_id, sub.descendant_id, parent.depth + sub.depth + 1
+    FROM category_closure parent
+    CROSS JOIN category_closure sub
+    WHERE parent.descendant_id = NEW.parent_id
+      AND sub.ancestor_id = NEW.doc_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION category_closure_delete() RETURNS trigger AS $$
+BEGIN
+    DELETE FROM category_closure WHERE descendant_id = OLD.doc_id OR ancestor_id = OLD.doc_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS category_closure_insert ON category;
+CREATE TRIGGER category_closure_insert
+    AFTER INSERT ON category
+    FOR EACH ROW EXECUTE FUNCTION category_closure_insert();
+
+DROP TRIGGER IF EXISTS category_closure_move ON category;
+CREATE TRIGGER category_closure_move
+    AFTER UPDATE OF parent_id ON category
+    FOR EACH ROW
+    WHEN (OLD.parent_id IS DISTINCT FROM NEW.parent_id)
+    EXECUTE FUNCTION category_closure_move();
+
+DROP TRIGGER IF EXISTS category_closure_delete ON category;
+CREATE TRIGGER category_closure_delete
+    AFTER DELETE ON category
+    FOR EACH ROW EXECUTE FUNCTION category_closure_delete();
+
+
+-- Initial fill from parent_id, rebuild_category_closure in category_tree.rs does the same per org
+INSERT INTO category_closure (org_id, ancestor_id, descendant_id, depth)
+WITH RECURSIVE tree AS (
+    SELECT org_id, doc_id AS ancestor_id, doc_id AS descendant_id, 0 AS depth
+    FROM category
+
+    UNION
+
+    SELECT t.org_id, t.ancestor_id, c.doc_id, t.depth + 1
+    FROM category c
+    JOIN tree t ON c.parent_id = t.descendant_id AND c.org_id = t.org_id
+    WHERE t.depth < 100
+)
+SELECT org_id, ancestor_id, descendant_id, MIN(depth)
+FROM tree
+GROUP BY org_id, ancestor_id, descendant_id
+ON CONFLICT (ancestor_id, descendant_id) DO NOTHING;
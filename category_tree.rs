@@ -0,0 +1,490 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio_postgres::GenericClient;
+
+// Set once category_closure.sql is seen applied. Not found is looked up
+// again, the migration can run while the process is up.
+static CATEGORY_CLOSURE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CategoryTreeItem {
+    pub id: String,
+    pub title: String,
+    pub parent_id: Option<String>,
+    // 0 for the category the subtree was asked for, or for root categories
+    pub depth: i32,
+    pub sort_no: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CategoryMoveInput {
+    // None moves the category to the root
+    pub parent_id: Option<String>,
+    // Position among the new siblings, None puts it last
+    pub position: Option<usize>,
+}
+
+/// What happens to the children when a category is soft deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CategoryDeleteMode {
+    /// Children take the place of the deleted category under its parent
+    MoveChildrenUp,
+    /// The whole subtree is deleted
+    Cascade,
+}
+
+/// Path from the root down to the category, the category itself last
+pub async fn get_category_breadcrumb(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    category_id: &str,
+) -> ApiResult<Vec<OptionItemString>> {
+    let rows = if has_category_closure(db).await? {
+        db.query(
+            "SELECT c.doc_id, c.title
+            FROM category_closure cc
+            JOIN category c ON c.doc_id = cc.ancestor_id
+            WHERE cc.descendant_id = $1 AND c.org_id = $2 AND c.is_deleted = false
+              AND EXISTS (
+                  SELECT 1 FROM category WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false
+              )
+            ORDER BY cc.depth DESC",
+            &[&category_id, &org_id],
+        )
+        .await?
+    } else {
+        db.query(
+            "WITH RECURSIVE category_path AS (
+                SELECT doc_id, title, parent_id, 1 AS depth
+                FROM category
+                WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false
+
+                UNION
+
+                SELECT c.doc_id, c.title, c.parent_id, cp.depth + 1
+                FROM category c
+                JOIN category_path cp ON c.doc_id = cp.parent_id
+                WHERE c.org_id = $2 AND c.is_deleted = false AND cp.depth < 100
+            )
+            SELECT doc_id, title FROM category_path ORDER BY depth DESC",
+            &[&category_id, &org_id],
+        )
+        .await?
+    };
+
+    if rows.is_empty() {
+        return Err(ApiError::Error("Invalid category id".to_string()));
+    }
+
+    Ok(rows
+        .iter()
+        .map(|row| OptionItemString {
+            id: row.get_as_string("doc_id"),
+            title: row.get_as_string("title"),
+        })
+        .collect())
+}
+
+/// The category and everything below it in display order (parents before
+/// children, siblings by sort_no). With no category the whole tree is returned.
+pub async fn get_category_subtree(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    category_id: Option<&str>,
+    max_depth: Option<i32>,
+) -> ApiResult<Vec<CategoryTreeItem>> {
+    let max_depth = max_depth.unwrap_or(100);
+    let rows = if has_category_closure(db).await? {
+        // Sort path from the ancestors of each row, skipping rows below a deleted
+        // category. depth <= d.depth includes the subtree root, like the recursive walk.
+        db.query(
+            "SELECT c.doc_id, c.title, c.parent_id, c.sort_no, d.depth,
+                ARRAY(
+                    SELECT lpad(a.sort_no::TEXT, 10, '0') || a.doc_id
+                    FROM category_closure ac
+                    JOIN category a ON a.doc_id = ac.ancestor_id
+                    WHERE ac.descendant_id = c.doc_id AND ac.depth <= d.depth
+                    ORDER BY ac.depth DESC
+                ) AS path
+            FROM category_closure d
+            JOIN category c ON c.doc_id = d.descendant_id
+            WHERE c.org_id = $1 AND c.is_deleted = false AND d.depth <= $3
+              AND CASE
+                  WHEN $2::VARCHAR IS NULL THEN d.ancestor_id IN (
+                      SELECT doc_id FROM category WHERE org_id = $1 AND parent_id IS NULL AND is_deleted = false
+                  )
+                  ELSE d.ancestor_id = $2
+              END
+              AND NOT EXISTS (
+                  SELECT 1
+                  FROM category_closure x
+                  JOIN category xa ON xa.doc_id = x.ancestor_id
+                  WHERE x.descendant_id = c.doc_id AND x.depth <= d.depth AND xa.is_deleted = true
+              )
+            ORDER BY path",
+            &[&org_id, &category_id, &max_depth],
+        )
+        .await?
+    } else {
+        db.query(
+            "WITH RECURSIVE category_tree AS (
+                SELECT doc_id, title, parent_id, sort_no, 0 AS depth,
+                    ARRAY[lpad(sort_no::TEXT, 10, '0') || doc_id] AS path
+                FROM category
+                WHERE org_id = $1 AND is_deleted = false
+                  AND CASE WHEN $2::VARCHAR IS NULL THEN parent_id IS NULL ELSE doc_id = $2 END
+
+                UNION ALL
+
+                SELECT c.doc_id, c.title, c.parent_id, c.sort_no, ct.depth + 1,
+                    ct.path || (lpad(c.sort_no::TEXT, 10, '0') || c.doc_id)
+                FROM category c
+                JOIN category_tree ct ON c.parent_id = ct.doc_id
+                WHERE c.org_id = $1 AND c.is_deleted = false
+                  AND ct.depth < $3
+                  -- Guards against cycles written before move_category existed
+                  AND NOT (lpad(c.sort_no::TEXT, 10, '0') || c.doc_id) = ANY(ct.path)
+            )
+            SELECT doc_id, title, parent_id, sort_no, depth FROM category_tree ORDER BY path",
+            &[&org_id, &category_id, &max_depth],
+        )
+        .await?
+    };
+
+    if rows.is_empty() && category_id.is_some() {
+        return Err(ApiError::Error("Invalid category id".to_string()));
+    }
+
+    Ok(rows
+        .iter()
+        .map(|row| CategoryTreeItem {
+            id: row.get_as_string("doc_id"),
+            title: row.get_as_string("title"),
+            parent_id: row.get("parent_id"),
+            depth: row.get("depth"),
+            sort_no: row.get("sort_no"),
+        })
+        .collect())
+}
+
+/// Moves a category (with its subtree) under another parent of the same org
+pub async fn move_category(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    category_id: &str,
+    input: &CategoryMoveInput,
+) -> ApiResult<()> {
+    // Two concurrent moves could each pass the cycle check and still form one together
+    lock_category_tree(db, org_id).await?;
+
+    let current_parent: Option<String> = db
+        .query_opt(
+            "SELECT parent_id FROM category WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+            &[&category_id, &org_id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::Error("Invalid category id".to_string()))?
+        .get("parent_id");
+
+    if let Some(parent_id) = &input.parent_id {
+        if parent_id == category_id {
+            return Err(ApiError::Error("A category cannot be its own parent".to_string()));
+        }
+
+        let parent = db
+            .query_opt(
+                "SELECT org_id FROM category WHERE doc_id = $1 AND is_deleted = false",
+                &[parent_id],
+            )
+            .await?
+            .ok_or_else(|| ApiError::Error("Invalid parent id".to_string()))?;
+        if parent.get_as_string("org_id") != org_id {
+            return Err(ApiError::Error(
+                "Parent category belongs to another organisation".to_string(),
+            ));
+        }
+
+        // Walk up from the new parent, meeting the category means it is a descendant
+        let cycle = if has_category_closure(db).await? {
+            db.query_one(
+                "SELECT EXISTS (
+                    SELECT 1 FROM category_closure WHERE ancestor_id = $2 AND descendant_id = $1
+                ) AS cycle",
+                &[parent_id, &category_id],
+            )
+            .await?
+        } else {
+            db.query_one(
+                "WITH RECURSIVE ancestors AS (
+                    SELECT doc_id, parent_id FROM category WHERE doc_id = $1 AND org_id = $3
+
+                    UNION
+
+                    SELECT c.doc_id, c.parent_id
+                    FROM category c
+                    JOIN ancestors a ON c.doc_id = a.parent_id
+                    WHERE c.org_id = $3
+                )
+                SELECT EXISTS (SELECT 1 FROM ancestors WHERE doc_id = $2) AS cycle",
+                &[parent_id, &category_id, &org_id],
+            )
+            .await?
+        };
+        if cycle.get::<_, bool>("cycle") {
+            return Err(ApiError::Error(
+                "A category cannot be moved below one of its own descendants".to_string(),
+            ));
+        }
+    }
+
+    db.execute(
+        "UPDATE category SET parent_id = $3, modified_on = current_timestamp
+        WHERE doc_id = $1 AND org_id = $2",
+        &[&category_id, &org_id, &input.parent_id],
+    )
+    .await?;
+
+    // Place it among the new siblings and close the gap it left behind
+    let mut siblings = get_child_ids(db, org_id, input.parent_id.as_deref()).await?;
+    siblings.retain(|id| id != category_id);
+    let position = input.position.unwrap_or(siblings.len()).min(siblings.len());
+    siblings.insert(position, category_id.to_string());
+    write_sort_no(db, org_id, &siblings).await?;
+
+    if current_parent != input.parent_id {
+        let old_siblings = get_child_ids(db, org_id, current_parent.as_deref()).await?;
+        write_sort_no(db, org_id, &old_siblings).await?;
+    }
+
+    Ok(())
+}
+
+/// Sets the order of the children of a parent (root when None).
+/// The ids must be exactly the current children.
+pub async fn reorder_categories(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    parent_id: Option<&str>,
+    category_ids: &[String],
+) -> ApiResult<()> {
+    lock_category_tree(db, org_id).await?;
+
+    let mut current = get_child_ids(db, org_id, parent_id).await?;
+    let mut given = category_ids.to_vec();
+    current.sort();
+    given.sort();
+    if current != given {
+        return Err(ApiError::Error(
+            "Category ids must be exactly the children of the parent".to_string(),
+        ));
+    }
+
+    write_sort_no(db, org_id, category_ids).await
+}
+
+/// Soft deletes a category, `mode` decides what happens to its children.
+/// Returns the ids of every category that was deleted.
+pub async fn delete_category(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    category_id: &str,
+    mode: CategoryDeleteMode,
+) -> ApiResult<Vec<String>> {
+    lock_category_tree(db, org_id).await?;
+
+    let parent_id: Option<String> = db
+        .query_opt(
+            "SELECT parent_id FROM category WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+            &[&category_id, &org_id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::Error("Invalid category id".to_string()))?
+        .get("parent_id");
+
+    let deleted: Vec<String> = match mode {
+        CategoryDeleteMode::MoveChildrenUp => {
+            // Children keep their order and go where the deleted category was
+            let mut siblings = get_child_ids(db, org_id, parent_id.as_deref()).await?;
+            let children = get_child_ids(db, org_id, Some(category_id)).await?;
+            let position = siblings.iter().position(|id| id == category_id).unwrap_or(0);
+            siblings.splice(position..=position, children);
+
+            db.execute(
+                "UPDATE category SET parent_id = $3, modified_on = current_timestamp
+                WHERE parent_id = $1 AND org_id = $2 AND is_deleted = false",
+                &[&category_id, &org_id, &parent_id],
+            )
+            .await?;
+            write_sort_no(db, org_id, &siblings).await?;
+
+            db.execute(
+                "UPDATE category SET is_deleted = true, modified_on = current_timestamp
+                WHERE doc_id = $1 AND org_id = $2",
+                &[&category_id, &org_id],
+            )
+            .await?;
+            vec![category_id.to_string()]
+        }
+        CategoryDeleteMode::Cascade => {
+            let query = if has_category_closure(db).await? {
+                "UPDATE category SET is_deleted = true, modified_on = current_timestamp
+                WHERE doc_id IN (SELECT descendant_id FROM category_closure WHERE ancestor_id = $1)
+                  AND org_id = $2 AND is_deleted = false
+                RETURNING doc_id"
+            } else {
+                "WITH RECURSIVE category_tree AS (
+                    SELECT doc_id FROM category WHERE doc_id = $1 AND org_id = $2
+
+                    UNION
+
+                    SELECT c.doc_id
+                    FROM category c
+                    JOIN category_tree ct ON c.parent_id = ct.doc_id
+                    WHERE c.org_id = $2 AND c.is_deleted = false
+                )
+                UPDATE category SET is_deleted = true, modified_on = current_timestamp
+                WHERE doc_id IN (SELECT doc_id FROM category_tree) AND org_id = $2
+                RETURNING doc_id"
+            };
+            let rows = db.query(query, &[&category_id, &org_id]).await?;
+            let siblings = get_child_ids(db, org_id, parent_id.as_deref()).await?;
+            write_sort_no(db, org_id, &siblings).await?;
+            rows.iter().map(|row| row.get_as_string("doc_id")).collect()
+        }
+    };
+
+    Ok(deleted)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CategoryClosureCheck {
+    // Pairs implied by parent_id but missing from the closure table
+    pub missing: i64,
+    // Pairs in the closure table that parent_id no longer implies
+    pub extra: i64,
+    pub rebuilt: bool,
+}
+
+/// Compares the closure table of an org against parent_id and rebuilds it
+/// when they disagree (or always with `force`)
+pub async fn rebuild_category_closure(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    force: bool,
+) -> ApiResult<CategoryClosureCheck> {
+    if !has_category_closure(db).await? {
+        return Err(ApiError::Error("Category closure table is not installed".to_string()));
+    }
+    lock_category_tree(db, org_id).await?;
+
+    let expected = "WITH RECURSIVE tree AS (
+            SELECT doc_id AS ancestor_id, doc_id AS descendant_id, 0 AS depth
+            FROM category
+            WHERE org_id = $1
+
+            UNION
+
+            SELECT t.ancestor_id, c.doc_id, t.depth + 1
+            FROM category c
+            JOIN tree t ON c.parent_id = t.descendant_id
+            WHERE c.org_id = $1 AND t.depth < 100
+        ),
+        expected AS (
+            SELECT ancestor_id, descendant_id, MIN(depth) AS depth
+            FROM tree
+            GROUP BY ancestor_id, descendant_id
+        )";
+
+    let row = db
+        .query_one(
+            &format!(
+                "{expected},
+                actual AS (
+                    SELECT ancestor_id, descendant_id, depth FROM category_closure WHERE org_id = $1
+                )
+                SELECT
+                    (SELECT COUNT(*) FROM (SELECT * FROM expected EXCEPT SELECT * FROM actual) m) AS missing,
+                    (SELECT COUNT(*) FROM (SELECT * FROM actual EXCEPT SELECT * FROM expected) e) AS extra"
+            ),
+            &[&org_id],
+        )
+        .await?;
+    let missing: i64 = row.get("missing");
+    let extra: i64 = row.get("extra");
+
+    let rebuilt = force || missing != 0 || extra != 0;
+    if rebuilt {
+        db.execute("DELETE FROM category_closure WHERE org_id = $1", &[&org_id])
+            .await?;
+        db.execute(
+            &format!(
+                "INSERT INTO category_closure (org_id, ancestor_id, descendant_id, depth)
+                {expected}
+                SELECT $1, ancestor_id, descendant_id, depth FROM expected"
+            ),
+            &[&org_id],
+        )
+        .await?;
+    }
+
+    Ok(CategoryClosureCheck {
+        missing,
+        extra,
+        rebuilt,
+    })
+}
+
+/// Once found the table is assumed to stay, until then every call looks it up
+pub async fn has_category_closure<C: GenericClient>(db: &C) -> ApiResult<bool> {
+    if CATEGORY_CLOSURE.load(Ordering::Relaxed) {
+        return Ok(true);
+    }
+    let row = db
+        .query_one(
+            "SELECT to_regclass('category_closure') IS NOT NULL AS enabled",
+            &[],
+        )
+        .await?;
+    let enabled: bool = row.get("enabled");
+    if enabled {
+        CATEGORY_CLOSURE.store(true, Ordering::Relaxed);
+    }
+    Ok(enabled)
+}
+
+/// Serialises tree changes of an org until the transaction ends
+async fn lock_category_tree(db: &DBConnection<'_>, org_id: &str) -> ApiResult<()> {
+    db.execute(
+        "SELECT pg_advisory_xact_lock(hashtext('category_tree:' || $1))",
+        &[&org_id],
+    )
+    .await?;
+    Ok(())
+}
+
+async fn get_child_ids(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    parent_id: Option<&str>,
+) -> ApiResult<Vec<String>> {
+    let rows = db
+        .query(
+            "SELECT doc_id FROM category
+            WHERE org_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND is_deleted = false
+            ORDER BY sort_no, title",
+            &[&org_id, &parent_id],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get_as_string("doc_id")).collect())
+}
+
+async fn write_sort_no(db: &DBConnection<'_>, org_id: &str, category_ids: &[String]) -> ApiResult<()> {
+    db.execute(
+        "UPDATE category c SET sort_no = o.sort_no::INT - 1
+        FROM unnest($1::VARCHAR[]) WITH ORDINALITY AS o(doc_id, sort_no)
+        WHERE c.doc_id = o.doc_id AND c.org_id = $2 AND c.sort_no <> o.sort_no::INT - 1",
+        &[&category_ids, &org_id],
+    )
+    .await?;
+    Ok(())
+}
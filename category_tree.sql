@@ -0,0 +1,9 @@
+-- Position of a category among its siblings
+ALTER TABLE category ADD COLUMN IF NOT EXISTS sort_no INT NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS category_org_parent
+    ON category (org_id, parent_id, sort_no);
+
+-- A category can never be its own parent, deeper cycles are rejected by move_category
+ALTER TABLE category DROP CONSTRAINT IF EXISTS category_parent_not_self;
+ALTER TABLE category ADD CONSTRAINT category_parent_not_self CHECK (parent_id IS NULL OR parent_id <> doc_id);
@@ -0,0 +1,207 @@
+//! `#[derive(FromRow)]` for structs read from a `tokio_postgres::Row`.
+//!
+//! The derive implements a `FromRow` trait that must be in scope where it is used:
+//!
+//! ```ignore
+//! pub trait FromRow: Sized {
+//!     fn from_row(row: &tokio_postgres::Row) -> Result<Self, tokio_postgres::Error>;
+//! }
+//! ```
+//!
+//! Field attributes:
+//! - `#[from_row(rename = "column")]` reads another column than the field name
+//! - `#[from_row(json)]` reads a JSON/JSONB column into any `Deserialize` type,
+//!   an `Option<T>` field is None for SQL NULL
+//! - `#[from_row(skip)]` leaves the field at `Default::default()`
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<String>,
+    json: bool,
+    skip: bool,
+}
+
+fn field_options(field: &syn::Field) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("from_row")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let value: LitStr = meta.value()?.parse()?;
+                options.rename = Some(value.value());
+                Ok(())
+            } else if meta.path.is_ident("json") {
+                options.json = true;
+                Ok(())
+            } else if meta.path.is_ident("skip") {
+                options.skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `rename = \"..\"`, `json` or `skip`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+/// T of an `Option<T>` field, also when written as std::option::Option<T>
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            syn::GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[proc_macro_derive(FromRow, attributes(from_row))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "FromRow needs a struct with named fields",
+                ))
+            }
+        },
+        _ => return Err(syn::Error::new_spanned(name, "FromRow only supports structs")),
+    };
+
+    let mut assignments = Vec::new();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        let options = field_options(field)?;
+
+        if options.skip {
+            assignments.push(quote! { #ident: ::std::default::Default::default() });
+            continue;
+        }
+
+        // r#type reads the column "type"
+        let column = options
+            .rename
+            .unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string());
+
+        if options.json {
+            // Json<Option<T>> would fail on NULL, it only sees JSON null
+            let assignment = match option_inner(ty) {
+                Some(inner) => quote! {
+                    #ident: row
+                        .try_get::<_, ::std::option::Option<::tokio_postgres::types::Json<#inner>>>(#column)?
+                        .map(|json| json.0)
+                },
+                None => quote! {
+                    #ident: row
+                        .try_get::<_, ::tokio_postgres::types::Json<#ty>>(#column)?
+                        .0
+                },
+            };
+            assignments.push(assignment);
+        } else {
+            assignments.push(quote! { #ident: row.try_get::<_, #ty>(#column)? });
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics FromRow for #name #ty_generics #where_clause {
+            fn from_row(row: &::tokio_postgres::Row) -> ::std::result::Result<Self, ::tokio_postgres::Error> {
+                ::std::result::Result::Ok(Self {
+                    #(#assignments,)*
+                })
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn expanded(input: DeriveInput) -> String {
+        expand(&input).unwrap().to_string().replace(' ', "")
+    }
+
+    #[test]
+    fn reads_renamed_and_skipped_fields() {
+        let code = expanded(parse_quote! {
+            struct Venue {
+                doc_id: String,
+                #[from_row(rename = "title")]
+                name: String,
+                r#type: i32,
+                #[from_row(skip)]
+                products: Vec<String>,
+            }
+        });
+        assert!(code.contains(r#"doc_id:row.try_get::<_,String>("doc_id")?"#));
+        assert!(code.contains(r#"name:row.try_get::<_,String>("title")?"#));
+        assert!(code.contains(r#"r#type:row.try_get::<_,i32>("type")?"#));
+        assert!(code.contains("products:::std::default::Default::default()"));
+    }
+
+    #[test]
+    fn reads_json_and_nullable_json() {
+        let code = expanded(parse_quote! {
+            struct Product {
+                #[from_row(json)]
+                price: Price,
+                #[from_row(json)]
+                discount: Option<Price>,
+                #[from_row(json)]
+                tax: std::option::Option<Vec<Price>>,
+            }
+        });
+        assert!(code.contains(
+            r#"price:row.try_get::<_,::tokio_postgres::types::Json<Price>>("price")?.0"#
+        ));
+        // SQL NULL is read as None instead of failing to deserialize
+        assert!(code.contains(
+            r#"discount:row.try_get::<_,::std::option::Option<::tokio_postgres::types::Json<Price>>>("discount")?.map(|json|json.0)"#
+        ));
+        assert!(code.contains(
+            r#"tax:row.try_get::<_,::std::option::Option<::tokio_postgres::types::Json<Vec<Price>>>>("tax")?.map(|json|json.0)"#
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_options_and_tuple_structs() {
+        let unknown: DeriveInput = parse_quote! {
+            struct Venue {
+                #[from_row(default)]
+                title: String,
+            }
+        };
+        assert!(expand(&unknown).is_err());
+
+        let tuple: DeriveInput = parse_quote! {
+            struct Venue(String);
+        };
+        assert!(expand(&tuple).is_err());
+    }
+}
@@ -2,16 +2,67 @@ use chrono::Local;
 use dialoguer::Select;
 use notify_rust::Notification;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf, time::Duration};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::time::interval;
 
+#[path = "work_log_client.rs"]
+mod work_log_client;
+
+use work_log_client::{employee_id, post_work_logs, read_json_file, write_json_file};
+
 #[derive(Serialize, Deserialize, Debug)]
 struct HabitEntry {
     timestamp: String,
     did_it: bool,
+    // Set once the attendance backend has accepted the entry
+    #[serde(default)]
+    synced: bool,
+}
+
+fn read_history(path: &Path) -> io::Result<Vec<HabitEntry>> {
+    read_json_file(path)
+}
+
+/// Post entries not yet synced to the attendance backend. The timestamp
+/// doubles as the client id, so retrying after a failure is harmless.
+async fn sync_history(
+    path: &Path,
+    employee_id: i64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let history = read_history(path)?;
+    let entries: Vec<serde_json::Value> = history
+        .iter()
+        .filter(|entry| !entry.synced)
+        .map(|entry| {
+            serde_json::json!({
+                "client_id": format!("habit-{}", entry.timestamp),
+                "logged_at": entry.timestamp,
+                "source": "habit",
+                "note": if entry.did_it { "Habit done" } else { "Habit skipped" },
+            })
+        })
+        .collect();
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let synced = post_work_logs(employee_id, &entries).await?;
+
+    let mut history = history;
+    for entry in history.iter_mut() {
+        if synced.contains(&format!("habit-{}", entry.timestamp)) {
+            entry.synced = true;
+        }
+    }
+    write_json_file(path, &history)?;
+    Ok(())
 }
 
-async fn ask_and_store(path: &PathBuf) {
+async fn ask_and_store(path: &Path) {
     // Send system notification
     Notification::new()
         .summary("Habit Check")
@@ -34,27 +85,36 @@ async fn ask_and_store(path: &PathBuf) {
     let entry = HabitEntry {
         timestamp: Local::now().to_rfc3339(),
         did_it: selection == 0,
+        synced: false,
     };
 
-    let mut history = if path.exists() {
-        let data = fs::read_to_string(path).unwrap_or_else(|_| "[]".into());
-        serde_json::from_str::<Vec<HabitEntry>>(&data).unwrap_or_else(|_| vec![])
-    } else {
-        vec![]
-    };
-
-    history.push(entry);
-    fs::write(path, serde_json::to_string_pretty(&history).unwrap()).unwrap();
+    // An unreadable history is left as it is rather than replaced with this entry
+    let stored = read_history(path).and_then(|mut history| {
+        history.push(entry);
+        write_json_file(path, &history)
+    });
+    if let Err(e) = stored {
+        eprintln!("Habit answer not stored, {}: {}", path.display(), e);
+    }
 }
 
 #[tokio::main]
 async fn main() {
     let path = PathBuf::from("habit_log.json");
+    // Entries are still recorded without an employee, they just stay unsynced
+    let employee_id = employee_id("habit log");
     let mut interval = interval(Duration::from_secs(60 * 60)); // every 1 hour
 
     loop {
         interval.tick().await;
         ask_and_store(&path).await;
+
+        // Offline entries stay unsynced and go with the next hour's attempt
+        if let Some(employee_id) = employee_id {
+            if let Err(e) = sync_history(&path, employee_id).await {
+                eprintln!("Habit log sync failed, will retry: {}", e);
+            }
+        }
     }
 }
 
@@ -66,4 +126,5 @@ serde_json = "1"
 notify-rust = "4"
 dialoguer = "0.11"
 chrono = "0.4"
+reqwest = { version = "0.11", features = ["json"] }
 
@@ -1,18 +1,86 @@
 use gtk::prelude::*;
 use gtk::{Application, ApplicationWindow, Entry, Button, Box as GtkBox, Orientation};
 use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
 use tray_item::TrayItem;
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
 use tokio::time::{sleep, Duration};
 
+#[path = "work_log_client.rs"]
+mod work_log_client;
+
+use work_log_client::{employee_id, post_work_logs, read_json_file, write_json_file};
+
+// Entries not yet accepted by the backend, kept across restarts
+const PENDING_FILE: &str = "hourly_log_pending.json";
+
+// Held for every read-modify-write of the pending file, the GTK thread
+// appends while the sync task removes what the backend accepted
+static PENDING_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct WorkLogEntry {
+    client_id: String,
+    logged_at: String,
+    source: String,
+    note: String,
+}
+
+fn read_pending() -> io::Result<Vec<WorkLogEntry>> {
+    read_json_file(Path::new(PENDING_FILE))
+}
+
+fn write_pending(entries: &[WorkLogEntry]) -> io::Result<()> {
+    write_json_file(Path::new(PENDING_FILE), entries)
+}
+
 fn save_log(text: &str) {
+    let now = chrono::Utc::now();
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
         .open("hourly_log.txt")
         .unwrap();
-    writeln!(file, "{} - {}", chrono::Local::now(), text).unwrap();
+    writeln!(file, "{} - {}", now.with_timezone(&chrono::Local), text).unwrap();
+
+    let _guard = PENDING_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    // The note is in hourly_log.txt either way, an unreadable queue is left
+    // as it is rather than replaced with this one entry
+    let queued = read_pending().and_then(|mut pending| {
+        pending.push(WorkLogEntry {
+            client_id: format!("hourly-{}", now.timestamp_nanos_opt().unwrap_or_default()),
+            logged_at: now.to_rfc3339(),
+            source: "hourly".to_string(),
+            note: text.to_string(),
+        });
+        write_pending(&pending)
+    });
+    if let Err(e) = queued {
+        eprintln!("Note not queued for sync, {}: {}", PENDING_FILE, e);
+    }
+}
+
+/// Post pending entries to the attendance backend, keep whatever did not go through
+async fn sync_pending(employee_id: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let pending = read_pending()?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let synced = post_work_logs(employee_id, &pending).await?;
+
+    // Re-read under the lock so notes saved while the request was in flight
+    // are kept, only the entries the backend accepted are removed
+    let _guard = PENDING_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let remaining: Vec<WorkLogEntry> = read_pending()?
+        .into_iter()
+        .filter(|entry| !synced.contains(&entry.client_id))
+        .collect();
+    write_pending(&remaining)?;
+    Ok(())
 }
 
 fn open_input_window(app: &Application) {
@@ -79,6 +147,19 @@ async fn main() {
         }
     });
 
+    // Offline entries are retried until the backend accepts them. Without an
+    // employee they stay in the pending file until the app is restarted with one.
+    if let Some(employee_id) = employee_id("work log") {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = sync_pending(employee_id).await {
+                    eprintln!("Work log sync failed, will retry: {}", e);
+                }
+                sleep(Duration::from_secs(300)).await;
+            }
+        });
+    }
+
     app.connect_activate(move |app| {
         // Empty window (hidden, app lives in tray)
         let window = ApplicationWindow::builder()
@@ -99,4 +180,7 @@ notify-rust = "4"
 gtk = { version = "0.9", package = "gtk4" }
 tray-item = "0.6"
 chrono = "0.4"
-tokio = { version = "1", features = ["full"] }
\ No newline at end of file
+tokio = { version = "1", features = ["full"] }
+serde = { version = "1", features = ["derive"] }
+serde_json = "1"
+reqwest = { version = "0.11", features = ["json"] }
\ No newline at end of file
@@ -0,0 +1,192 @@
+use crate::{execute_cached, execute_checked_cached, query_cached, query_one_cached, DbError, FromRow};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const ENTITY: &str = "category";
+
+const SELECT: &str = "SELECT doc_id, org_id, parent_id, title, sort_no, doc FROM category";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, FromRow)]
+pub struct Category {
+    pub doc_id: String,
+    pub org_id: String,
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub sort_no: i32,
+    pub doc: Value,
+}
+
+/// Categories of one org. Moving a category to another parent goes through
+/// category_tree::move_category, which checks for cycles, so update leaves
+/// parent_id alone.
+pub struct CategoryRepository<'a, C: GenericClient> {
+    db: &'a C,
+}
+
+impl<'a, C: GenericClient> CategoryRepository<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    pub async fn get(&self, org_id: &str, doc_id: &str) -> Result<Category, DbError> {
+        query_one_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            &format!("{} WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false", SELECT),
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+
+    /// Direct children, root categories when `parent_id` is None
+    pub async fn list_children(&self, org_id: &str, parent_id: Option<&str>) -> Result<Vec<Category>, DbError> {
+        query_cached(
+            self.db,
+            ENTITY,
+            &format!(
+                "{} WHERE org_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND is_deleted = false
+                ORDER BY sort_no, doc_id",
+                SELECT
+            ),
+            &[&org_id, &parent_id],
+        )
+        .await
+    }
+
+    /// Fails with InvalidReference when the parent is deleted or belongs to another org
+    pub async fn insert(&self, category: &Category) -> Result<(), DbError> {
+        execute_checked_cached(
+            self.db,
+            ENTITY,
+            &category.doc_id,
+            "category_parent_org",
+            "WITH parent AS (
+                SELECT $3::TEXT IS NULL OR EXISTS (
+                    SELECT 1 FROM category WHERE doc_id = $3 AND org_id = $2 AND is_deleted = false
+                ) AS ok
+            ),
+            inserted AS (
+                INSERT INTO category (doc_id, org_id, parent_id, title, sort_no, doc)
+                SELECT $1, $2, $3, $4, $5, $6 FROM parent WHERE ok
+                RETURNING 1
+            )
+            SELECT (SELECT ok FROM parent) AS reference_ok, (SELECT count(*) FROM inserted) AS affected",
+            &[
+                &category.doc_id,
+                &category.org_id,
+                &category.parent_id,
+                &category.title,
+                &category.sort_no,
+                &category.doc,
+            ],
+        )
+        .await
+    }
+
+    pub async fn update(&self, category: &Category) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            &category.doc_id,
+            "UPDATE category
+            SET title = $3, sort_no = $4, doc = $5, modified_on = current_timestamp
+            WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+            &[
+                &category.doc_id,
+                &category.org_id,
+                &category.title,
+                &category.sort_no,
+                &category.doc,
+            ],
+        )
+        .await
+    }
+
+    /// Soft delete of this category only, see category_tree::delete_category
+    /// for what happens to its children
+    pub async fn delete(&self, org_id: &str, doc_id: &str) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            "UPDATE category SET is_deleted = true, modified_on = current_timestamp
+            WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::repository_conn;
+
+    fn category(doc_id: &str, org_id: &str, parent_id: Option<&str>) -> Category {
+        Category {
+            doc_id: doc_id.to_string(),
+            org_id: org_id.to_string(),
+            parent_id: parent_id.map(str::to_string),
+            title: doc_id.to_uppercase(),
+            sort_no: 0,
+            doc: serde_json::json!({ "icon": "cup" }),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn insert_get_update_delete() {
+        let conn = repository_conn().await;
+        let repo = CategoryRepository::new(&conn);
+
+        let drinks = category("drinks", "org-1", None);
+        let mut beer = category("beer", "org-1", Some("drinks"));
+        repo.insert(&drinks).await.unwrap();
+        repo.insert(&beer).await.unwrap();
+        assert_eq!(repo.get("org-1", "beer").await.unwrap(), beer);
+        assert_eq!(repo.list_children("org-1", None).await.unwrap(), vec![drinks.clone()]);
+        assert_eq!(repo.list_children("org-1", Some("drinks")).await.unwrap(), vec![beer.clone()]);
+
+        let error = repo.insert(&drinks).await.unwrap_err();
+        assert!(matches!(error, DbError::Duplicate { entity: "category", .. }), "{}", error);
+
+        let error = repo.get("org-2", "drinks").await.unwrap_err();
+        assert!(matches!(error, DbError::NotFound { entity: "category", .. }), "{}", error);
+
+        beer.title = "Beer & cider".to_string();
+        beer.sort_no = 2;
+        repo.update(&beer).await.unwrap();
+        assert_eq!(repo.get("org-1", "beer").await.unwrap(), beer);
+
+        repo.delete("org-1", "beer").await.unwrap();
+        assert!(matches!(repo.get("org-1", "beer").await, Err(DbError::NotFound { .. })));
+        assert!(matches!(repo.update(&beer).await, Err(DbError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn insert_below_a_parent_of_another_org_or_deleted_is_invalid_reference() {
+        let conn = repository_conn().await;
+        let repo = CategoryRepository::new(&conn);
+        repo.insert(&category("drinks", "org-1", None)).await.unwrap();
+        repo.insert(&category("food", "org-1", None)).await.unwrap();
+        repo.delete("org-1", "food").await.unwrap();
+
+        for child in [
+            category("beer", "org-2", Some("drinks")),
+            category("bread", "org-1", Some("food")),
+            category("wine", "org-1", Some("missing")),
+        ] {
+            let error = repo.insert(&child).await.unwrap_err();
+            assert!(
+                matches!(error, DbError::InvalidReference { entity: "category", .. }),
+                "{}: {}",
+                child.doc_id,
+                error
+            );
+            assert!(matches!(repo.get(&child.org_id, &child.doc_id).await, Err(DbError::NotFound { .. })));
+        }
+    }
+}
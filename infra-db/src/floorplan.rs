@@ -0,0 +1,189 @@
+use crate::{execute_cached, execute_checked_cached, query_cached, query_one_cached, DbError, FromRow};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+
+const ENTITY: &str = "floorplan";
+
+const SELECT: &str = "SELECT doc_id, org_id, venue_id, title, no_rooms FROM floorplan";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, FromRow)]
+pub struct Floorplan {
+    pub doc_id: String,
+    pub org_id: String,
+    pub venue_id: String,
+    pub title: String,
+    pub no_rooms: i32,
+}
+
+pub struct FloorplanRepository<'a, C: GenericClient> {
+    db: &'a C,
+}
+
+impl<'a, C: GenericClient> FloorplanRepository<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    pub async fn get(&self, org_id: &str, doc_id: &str) -> Result<Floorplan, DbError> {
+        query_one_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            &format!("{} WHERE doc_id = $1 AND org_id = $2", SELECT),
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+
+    pub async fn list_by_venue(&self, org_id: &str, venue_id: &str) -> Result<Vec<Floorplan>, DbError> {
+        query_cached(
+            self.db,
+            ENTITY,
+            &format!(
+                "{} WHERE org_id = $1 AND venue_id = $2 ORDER BY title, doc_id",
+                SELECT
+            ),
+            &[&org_id, &venue_id],
+        )
+        .await
+    }
+
+    /// Fails with InvalidReference when the venue belongs to another org
+    pub async fn insert(&self, floorplan: &Floorplan) -> Result<(), DbError> {
+        execute_checked_cached(
+            self.db,
+            ENTITY,
+            &floorplan.doc_id,
+            "floorplan_venue_org",
+            "WITH venue AS (
+                SELECT EXISTS (SELECT 1 FROM venue WHERE doc_id = $3 AND org_id = $2) AS ok
+            ),
+            inserted AS (
+                INSERT INTO floorplan (doc_id, org_id, venue_id, title, no_rooms)
+                SELECT $1, $2, $3, $4, $5 FROM venue WHERE ok
+                RETURNING 1
+            )
+            SELECT (SELECT ok FROM venue) AS reference_ok, (SELECT count(*) FROM inserted) AS affected",
+            &[
+                &floorplan.doc_id,
+                &floorplan.org_id,
+                &floorplan.venue_id,
+                &floorplan.title,
+                &floorplan.no_rooms,
+            ],
+        )
+        .await
+    }
+
+    /// Same venue check as insert, checked before the floorplan itself
+    pub async fn update(&self, floorplan: &Floorplan) -> Result<(), DbError> {
+        execute_checked_cached(
+            self.db,
+            ENTITY,
+            &floorplan.doc_id,
+            "floorplan_venue_org",
+            "WITH venue AS (
+                SELECT EXISTS (SELECT 1 FROM venue WHERE doc_id = $3 AND org_id = $2) AS ok
+            ),
+            updated AS (
+                UPDATE floorplan SET venue_id = $3, title = $4, no_rooms = $5
+                WHERE doc_id = $1 AND org_id = $2 AND (SELECT ok FROM venue)
+                RETURNING 1
+            )
+            SELECT (SELECT ok FROM venue) AS reference_ok, (SELECT count(*) FROM updated) AS affected",
+            &[
+                &floorplan.doc_id,
+                &floorplan.org_id,
+                &floorplan.venue_id,
+                &floorplan.title,
+                &floorplan.no_rooms,
+            ],
+        )
+        .await
+    }
+
+    pub async fn delete(&self, org_id: &str, doc_id: &str) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            "DELETE FROM floorplan WHERE doc_id = $1 AND org_id = $2",
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::repository_conn;
+
+    fn floorplan(doc_id: &str, venue_id: &str) -> Floorplan {
+        Floorplan {
+            doc_id: doc_id.to_string(),
+            org_id: "org-1".to_string(),
+            venue_id: venue_id.to_string(),
+            title: "Ground floor".to_string(),
+            no_rooms: 3,
+        }
+    }
+
+    async fn insert_venues(conn: &crate::DB) {
+        conn.batch_execute(
+            "INSERT INTO venue (doc_id, org_id, title) VALUES
+                ('v1', 'org-1', 'Main hall'),
+                ('v2', 'org-1', 'Side hall'),
+                ('v3', 'org-2', 'Other org')",
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn insert_get_update_delete() {
+        let conn = repository_conn().await;
+        insert_venues(&conn).await;
+        let repo = FloorplanRepository::new(&conn);
+
+        let mut ground = floorplan("f1", "v1");
+        repo.insert(&ground).await.unwrap();
+        assert_eq!(repo.get("org-1", "f1").await.unwrap(), ground);
+
+        let error = repo.insert(&ground).await.unwrap_err();
+        assert!(matches!(error, DbError::Duplicate { entity: "floorplan", .. }), "{}", error);
+
+        let error = repo.get("org-2", "f1").await.unwrap_err();
+        assert!(matches!(error, DbError::NotFound { entity: "floorplan", .. }), "{}", error);
+
+        ground.venue_id = "v2".to_string();
+        ground.no_rooms = 5;
+        repo.update(&ground).await.unwrap();
+        assert_eq!(repo.list_by_venue("org-1", "v2").await.unwrap(), vec![ground.clone()]);
+        assert!(repo.list_by_venue("org-1", "v1").await.unwrap().is_empty());
+
+        repo.delete("org-1", "f1").await.unwrap();
+        assert!(matches!(repo.get("org-1", "f1").await, Err(DbError::NotFound { .. })));
+        assert!(matches!(repo.update(&ground).await, Err(DbError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn venue_of_another_org_is_invalid_reference() {
+        let conn = repository_conn().await;
+        insert_venues(&conn).await;
+        let repo = FloorplanRepository::new(&conn);
+
+        let error = repo.insert(&floorplan("f1", "v3")).await.unwrap_err();
+        assert!(matches!(error, DbError::InvalidReference { entity: "floorplan", .. }), "{}", error);
+        assert!(matches!(repo.get("org-1", "f1").await, Err(DbError::NotFound { .. })));
+
+        let mut ground = floorplan("f1", "v1");
+        repo.insert(&ground).await.unwrap();
+        ground.venue_id = "v3".to_string();
+        let error = repo.update(&ground).await.unwrap_err();
+        assert!(matches!(error, DbError::InvalidReference { entity: "floorplan", .. }), "{}", error);
+        assert_eq!(repo.get("org-1", "f1").await.unwrap().venue_id, "v1");
+    }
+}
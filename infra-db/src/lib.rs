@@ -0,0 +1,296 @@
+// Libs - DB - Manager
+
+use config::Config;
+use deadpool_postgres::{GenericClient, Manager, ManagerConfig, Pool, RecyclingMethod};
+use serde::Deserialize;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+use tokio_postgres::error::SqlState;
+use tracing::{debug, info};
+
+pub mod category;
+pub mod floorplan;
+pub mod product;
+pub mod retry;
+pub mod venue;
+
+#[cfg(test)]
+mod test_support;
+
+pub use category::{Category, CategoryRepository};
+pub use floorplan::{Floorplan, FloorplanRepository};
+pub use from_row_derive::FromRow;
+pub use product::{Product, ProductRepository};
+pub use retry::{retry_metrics, retry_transaction, ErrorClass, RetryMetrics, RetryPolicy};
+pub use venue::{Venue, VenueRepository};
+
+// Re-export useful types for downstream usage
+pub use deadpool_postgres::Transaction;
+pub use tokio_postgres::{types::ToSql, Row};
+
+// --- TYPE ALIASES ---
+
+/// Alias for the Database Connection Pool
+pub type DBPool = Pool;
+
+/// Alias for a pooled Database Connection, derefs to tokio_postgres::Client
+pub type DB = deadpool_postgres::Client;
+
+// --- ERROR HANDLING ---
+
+/// Unified Error type for database operations.
+#[derive(Debug, Error)]
+pub enum DbError {
+    /// Error acquiring a connection from the pool.
+    #[error("Failed to get connection from pool: {0}")]
+    PoolError(#[from] deadpool_postgres::PoolError),
+
+    /// Missing or malformed configuration.
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    /// No row with this id, or it belongs to another org.
+    #[error("{entity} {id} not found")]
+    NotFound { entity: &'static str, id: String },
+
+    /// Unique constraint violated, e.g. the same doc_id inserted twice.
+    #[error("{entity} already exists ({constraint})")]
+    Duplicate {
+        entity: &'static str,
+        constraint: String,
+    },
+
+    /// Foreign key violated, e.g. a floorplan for an unknown venue.
+    #[error("{entity} references a missing row ({constraint})")]
+    InvalidReference {
+        entity: &'static str,
+        constraint: String,
+    },
+
+    /// Database driver error (SQL syntax, connection loss, etc.).
+    #[error("Database driver error: {0}")]
+    PostgresError(#[from] tokio_postgres::Error),
+
+    /// Connection lost during COMMIT, the transaction may or may not have been applied.
+    #[error("Commit outcome unknown: {0}")]
+    CommitUnknown(tokio_postgres::Error),
+}
+
+impl From<config::ConfigError> for DbError {
+    fn from(e: config::ConfigError) -> Self {
+        DbError::ConfigError(e.to_string())
+    }
+}
+
+impl DbError {
+    /// Constraint violations become typed errors for `entity`, the rest stay driver errors
+    pub fn from_postgres(entity: &'static str, e: tokio_postgres::Error) -> Self {
+        let constraint = || {
+            e.as_db_error()
+                .and_then(|db| db.constraint())
+                .unwrap_or("")
+                .to_string()
+        };
+        match e.code() {
+            Some(code) if *code == SqlState::UNIQUE_VIOLATION => DbError::Duplicate {
+                entity,
+                constraint: constraint(),
+            },
+            Some(code) if *code == SqlState::FOREIGN_KEY_VIOLATION => DbError::InvalidReference {
+                entity,
+                constraint: constraint(),
+            },
+            _ => DbError::PostgresError(e),
+        }
+    }
+}
+
+// --- CONFIGURATION ---
+
+/// Database configuration loaded from environment variables or a file.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DBConfig {
+    pub url: String,
+    // Deadpool default (cpu count * 4) when not set
+    pub pool_max_size: Option<usize>,
+}
+
+impl DBConfig {
+    /// Loads database configuration from environment variables using default prefix "DB".
+    pub fn new() -> Result<DBConfig, DbError> {
+        Self::new_with_prefix("DB")
+    }
+
+    /// Loads configuration with a custom prefix (e.g., "AUTH_DB").
+    pub fn new_with_prefix(prefix: &str) -> Result<DBConfig, DbError> {
+        info!("Loading DBConfig from environment variables with prefix: {}", prefix);
+        let config = Config::builder()
+            .add_source(environment(prefix))
+            .build()?;
+        Ok(config.try_deserialize()?)
+    }
+
+    /// Loads configuration from a toml or json file, DB_ environment
+    /// variables override the file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<DBConfig, DbError> {
+        let path = path.as_ref();
+        info!("Loading DBConfig from {}", path.display());
+        let config = Config::builder()
+            .add_source(config::File::from(path))
+            .add_source(environment("DB"))
+            .build()?;
+        Ok(config.try_deserialize()?)
+    }
+}
+
+fn environment(prefix: &str) -> config::Environment {
+    config::Environment::with_prefix(prefix)
+        .try_parsing(true)
+        .prefix_separator("_")
+}
+
+// --- MANAGER STRUCT ---
+
+/// Manages the database connection pool.
+#[derive(Clone, Debug)]
+pub struct DBManager {
+    pool: DBPool,
+}
+
+impl DBManager {
+    /// Initializes a new database manager with connection pooling using Deadpool.
+    pub fn new(config: &DBConfig) -> Result<Self, DbError> {
+        info!("Initializing DBManager with Deadpool");
+
+        let pg_config = tokio_postgres::Config::from_str(&config.url)
+            .map_err(|e| DbError::ConfigError(format!("Invalid DB URL: {}", e)))?;
+
+        // Fast recycling keeps the connection, and with it the prepared
+        // statement cache, between checkouts
+        let mgr_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+        let mgr = Manager::from_config(pg_config, tokio_postgres::NoTls, mgr_config);
+
+        let mut builder = Pool::builder(mgr);
+        if let Some(max_size) = config.pool_max_size {
+            builder = builder.max_size(max_size);
+        }
+        let pool = builder
+            .build()
+            .map_err(|e| DbError::ConfigError(format!("Failed to create pool: {}", e)))?;
+
+        debug!("DBManager: Deadpool connection pool created");
+        Ok(Self { pool })
+    }
+
+    /// Retrieves a pooled database connection.
+    pub async fn get_conn(&self) -> Result<DB, DbError> {
+        Ok(self.pool.get().await?)
+    }
+
+    pub fn pool(&self) -> &DBPool {
+        &self.pool
+    }
+}
+
+// --- ROW MAPPING ---
+
+/// Typed mapping from a row, implemented by #[derive(FromRow)]
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, tokio_postgres::Error>;
+}
+
+// Shared by the repositories, every statement goes through the per-connection
+// statement cache so a query is parsed once per connection
+
+pub(crate) async fn query_cached<C: GenericClient, T: FromRow>(
+    db: &C,
+    entity: &'static str,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<Vec<T>, DbError> {
+    let statement = db.prepare_cached(sql).await?;
+    let rows = db
+        .query(&statement, params)
+        .await
+        .map_err(|e| DbError::from_postgres(entity, e))?;
+    Ok(rows
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+pub(crate) async fn query_one_cached<C: GenericClient, T: FromRow>(
+    db: &C,
+    entity: &'static str,
+    id: &str,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<T, DbError> {
+    let statement = db.prepare_cached(sql).await?;
+    let row = db
+        .query_opt(&statement, params)
+        .await
+        .map_err(|e| DbError::from_postgres(entity, e))?
+        .ok_or_else(|| DbError::NotFound {
+            entity,
+            id: id.to_string(),
+        })?;
+    Ok(T::from_row(&row)?)
+}
+
+/// Execute that treats zero affected rows as NotFound
+pub(crate) async fn execute_cached<C: GenericClient>(
+    db: &C,
+    entity: &'static str,
+    id: &str,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<(), DbError> {
+    let statement = db.prepare_cached(sql).await?;
+    let affected = db
+        .execute(&statement, params)
+        .await
+        .map_err(|e| DbError::from_postgres(entity, e))?;
+    if affected == 0 {
+        return Err(DbError::NotFound {
+            entity,
+            id: id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Execute of a write guarded by a check that a referenced row belongs to the
+/// same org. `sql` returns one row with `reference_ok`, the outcome of that
+/// check, and `affected`. A failed check is InvalidReference for `reference`,
+/// zero affected rows NotFound.
+pub(crate) async fn execute_checked_cached<C: GenericClient>(
+    db: &C,
+    entity: &'static str,
+    id: &str,
+    reference: &'static str,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<(), DbError> {
+    let statement = db.prepare_cached(sql).await?;
+    let row = db
+        .query_one(&statement, params)
+        .await
+        .map_err(|e| DbError::from_postgres(entity, e))?;
+    if !row.try_get::<_, bool>("reference_ok")? {
+        return Err(DbError::InvalidReference {
+            entity,
+            constraint: reference.to_string(),
+        });
+    }
+    if row.try_get::<_, i64>("affected")? == 0 {
+        return Err(DbError::NotFound {
+            entity,
+            id: id.to_string(),
+        });
+    }
+    Ok(())
+}
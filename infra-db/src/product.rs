@@ -0,0 +1,174 @@
+use crate::{execute_cached, query_cached, query_one_cached, DbError, FromRow};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+
+const ENTITY: &str = "product";
+
+// price is NUMERIC, read and written as FLOAT8 like the product filters do
+const SELECT: &str = "SELECT doc_id, org_id, title, categories, tags, price::FLOAT8 AS price, has_alcohol, is_archive
+    FROM product";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, FromRow)]
+pub struct Product {
+    pub doc_id: String,
+    pub org_id: String,
+    pub title: String,
+    pub categories: Vec<String>,
+    pub tags: Vec<String>,
+    pub price: Option<f64>,
+    pub has_alcohol: bool,
+    pub is_archive: bool,
+}
+
+/// Products of one org, deleted products are never returned
+pub struct ProductRepository<'a, C: GenericClient> {
+    db: &'a C,
+}
+
+impl<'a, C: GenericClient> ProductRepository<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    pub async fn get(&self, org_id: &str, doc_id: &str) -> Result<Product, DbError> {
+        query_one_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            &format!("{} WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false", SELECT),
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+
+    pub async fn list(&self, org_id: &str, limit: i64, offset: i64) -> Result<Vec<Product>, DbError> {
+        query_cached(
+            self.db,
+            ENTITY,
+            &format!(
+                "{} WHERE org_id = $1 AND is_deleted = false ORDER BY title, doc_id LIMIT $2 OFFSET $3",
+                SELECT
+            ),
+            &[&org_id, &limit, &offset],
+        )
+        .await
+    }
+
+    pub async fn list_by_category(&self, org_id: &str, category_id: &str) -> Result<Vec<Product>, DbError> {
+        query_cached(
+            self.db,
+            ENTITY,
+            &format!(
+                "{} WHERE org_id = $1 AND $2 = ANY(categories) AND is_deleted = false ORDER BY title, doc_id",
+                SELECT
+            ),
+            &[&org_id, &category_id],
+        )
+        .await
+    }
+
+    pub async fn insert(&self, product: &Product) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            &product.doc_id,
+            "INSERT INTO product (doc_id, org_id, title, categories, tags, price, has_alcohol, is_archive)
+            VALUES ($1, $2, $3, $4, $5, $6::FLOAT8, $7, $8)",
+            &[
+                &product.doc_id,
+                &product.org_id,
+                &product.title,
+                &product.categories,
+                &product.tags,
+                &product.price,
+                &product.has_alcohol,
+                &product.is_archive,
+            ],
+        )
+        .await
+    }
+
+    pub async fn update(&self, product: &Product) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            &product.doc_id,
+            "UPDATE product
+            SET title = $3, categories = $4, tags = $5, price = $6::FLOAT8, has_alcohol = $7,
+                is_archive = $8, modified_on = current_timestamp
+            WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+            &[
+                &product.doc_id,
+                &product.org_id,
+                &product.title,
+                &product.categories,
+                &product.tags,
+                &product.price,
+                &product.has_alcohol,
+                &product.is_archive,
+            ],
+        )
+        .await
+    }
+
+    /// Soft delete, the row stays for history and smart collections
+    pub async fn delete(&self, org_id: &str, doc_id: &str) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            "UPDATE product SET is_deleted = true, modified_on = current_timestamp
+            WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::repository_conn;
+
+    fn product(doc_id: &str) -> Product {
+        Product {
+            doc_id: doc_id.to_string(),
+            org_id: "org-1".to_string(),
+            title: "Lager".to_string(),
+            categories: vec!["drinks".to_string()],
+            tags: vec!["cold".to_string()],
+            price: Some(4.5),
+            has_alcohol: true,
+            is_archive: false,
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn insert_get_update_delete() {
+        let conn = repository_conn().await;
+        let repo = ProductRepository::new(&conn);
+
+        let mut lager = product("p1");
+        repo.insert(&lager).await.unwrap();
+        assert_eq!(repo.get("org-1", "p1").await.unwrap(), lager);
+        assert_eq!(repo.list_by_category("org-1", "drinks").await.unwrap(), vec![lager.clone()]);
+
+        let error = repo.insert(&lager).await.unwrap_err();
+        assert!(matches!(error, DbError::Duplicate { entity: "product", .. }), "{}", error);
+
+        // Another org does not see it
+        let error = repo.get("org-2", "p1").await.unwrap_err();
+        assert!(matches!(error, DbError::NotFound { entity: "product", .. }), "{}", error);
+
+        lager.price = None;
+        lager.is_archive = true;
+        repo.update(&lager).await.unwrap();
+        assert_eq!(repo.get("org-1", "p1").await.unwrap(), lager);
+
+        repo.delete("org-1", "p1").await.unwrap();
+        assert!(matches!(repo.get("org-1", "p1").await, Err(DbError::NotFound { .. })));
+        assert!(matches!(repo.update(&lager).await, Err(DbError::NotFound { .. })));
+        assert!(matches!(repo.delete("org-1", "p1").await, Err(DbError::NotFound { .. })));
+    }
+}
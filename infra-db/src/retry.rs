@@ -0,0 +1,446 @@
+// Libs - DB - Retry
+
+use crate::{DBManager, DbError, Transaction};
+use deadpool_postgres::PoolError;
+use rand::Rng;
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::IsolationLevel;
+use tracing::warn;
+
+/// Whether running the whole transaction again can succeed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    Retryable,
+    Fatal,
+}
+
+/// Serialization failures, deadlocks, lock timeouts and lost connections are
+/// retryable, everything else (constraint violations, bad SQL, ...) is fatal.
+pub fn classify(e: &tokio_postgres::Error) -> ErrorClass {
+    let code = match e.code() {
+        Some(code) => code,
+        // No SQLSTATE: the connection broke, or a client side error such as
+        // a type mismatch that will fail the same way again
+        None if e.is_closed() || is_io_error(e) => return ErrorClass::Retryable,
+        None => return ErrorClass::Fatal,
+    };
+
+    // Class 08 is connection exceptions
+    if code.code().starts_with("08") {
+        return ErrorClass::Retryable;
+    }
+    let retryable = [
+        SqlState::T_R_SERIALIZATION_FAILURE,
+        SqlState::T_R_DEADLOCK_DETECTED,
+        SqlState::LOCK_NOT_AVAILABLE,
+        SqlState::TOO_MANY_CONNECTIONS,
+        SqlState::ADMIN_SHUTDOWN,
+        SqlState::CRASH_SHUTDOWN,
+        SqlState::CANNOT_CONNECT_NOW,
+    ];
+    if retryable.contains(code) {
+        ErrorClass::Retryable
+    } else {
+        ErrorClass::Fatal
+    }
+}
+
+fn is_io_error(e: &tokio_postgres::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(error) = source {
+        if error.is::<std::io::Error>() {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+impl DbError {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            DbError::PostgresError(e) => classify(e),
+            DbError::PoolError(PoolError::Timeout(_)) => ErrorClass::Retryable,
+            DbError::PoolError(PoolError::Backend(e)) => classify(e),
+            _ => ErrorClass::Fatal,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.class() == ErrorClass::Retryable
+    }
+
+    fn sqlstate(&self) -> &str {
+        match self {
+            DbError::PostgresError(e)
+            | DbError::PoolError(PoolError::Backend(e))
+            | DbError::CommitUnknown(e) => {
+                e.code().map(|code| code.code()).unwrap_or("connection")
+            }
+            DbError::PoolError(_) => "pool",
+            _ => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Attempts in total, including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Isolation of each attempt, the database default when None
+    pub isolation_level: Option<IsolationLevel>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            isolation_level: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Serializable transactions fail with 40001 by design, so they get the default retries
+    pub fn serializable() -> Self {
+        Self {
+            isolation_level: Some(IsolationLevel::Serializable),
+            ..Self::default()
+        }
+    }
+
+    /// Exponential backoff capped at max_delay, half of it random so
+    /// transactions that collided do not retry in lockstep
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        let half = exp / 2;
+        half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+// --- METRICS ---
+
+#[derive(Debug)]
+struct RetryCounters {
+    transactions: AtomicU64,
+    retries: AtomicU64,
+    recovered: AtomicU64,
+    exhausted: AtomicU64,
+    fatal: AtomicU64,
+}
+
+static RETRY_COUNTERS: RetryCounters = RetryCounters {
+    transactions: AtomicU64::new(0),
+    retries: AtomicU64::new(0),
+    recovered: AtomicU64::new(0),
+    exhausted: AtomicU64::new(0),
+    fatal: AtomicU64::new(0),
+};
+
+/// Process wide counters since start, e.g. for the health endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RetryMetrics {
+    pub transactions: u64,
+    // Attempts after the first one
+    pub retries: u64,
+    // Transactions that succeeded after at least one retry
+    pub recovered: u64,
+    // Transactions that still failed with a retryable error on the last attempt
+    pub exhausted: u64,
+    // Transactions that failed with a fatal error
+    pub fatal: u64,
+}
+
+pub fn retry_metrics() -> RetryMetrics {
+    RetryMetrics {
+        transactions: RETRY_COUNTERS.transactions.load(Ordering::Relaxed),
+        retries: RETRY_COUNTERS.retries.load(Ordering::Relaxed),
+        recovered: RETRY_COUNTERS.recovered.load(Ordering::Relaxed),
+        exhausted: RETRY_COUNTERS.exhausted.load(Ordering::Relaxed),
+        fatal: RETRY_COUNTERS.fatal.load(Ordering::Relaxed),
+    }
+}
+
+// --- TRANSACTIONS ---
+
+pub type TransactionFuture<'t, T> = Pin<Box<dyn Future<Output = Result<T, DbError>> + Send + 't>>;
+
+/// Run `f` in a transaction on a pooled connection and commit, running the
+/// whole transaction again on a new connection when it fails with a
+/// retryable error. `f` must not have side effects outside the database.
+///
+/// ```ignore
+/// let moved = retry_transaction(&manager, &RetryPolicy::default(), "move_category", |tx| {
+///     Box::pin(async move {
+///         let categories = CategoryRepository::new(tx);
+///         ...
+///         Ok(())
+///     })
+/// })
+/// .await?;
+/// ```
+pub async fn retry_transaction<T, F>(
+    manager: &DBManager,
+    policy: &RetryPolicy,
+    operation: &'static str,
+    mut f: F,
+) -> Result<T, DbError>
+where
+    F: for<'t> FnMut(&'t Transaction<'t>) -> TransactionFuture<'t, T>,
+{
+    RETRY_COUNTERS.transactions.fetch_add(1, Ordering::Relaxed);
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        let error = match run_attempt(manager, policy, &mut f).await {
+            Ok(value) => {
+                if attempt > 1 {
+                    RETRY_COUNTERS.recovered.fetch_add(1, Ordering::Relaxed);
+                }
+                return Ok(value);
+            }
+            Err(error) => error,
+        };
+
+        if !error.is_retryable() {
+            RETRY_COUNTERS.fatal.fetch_add(1, Ordering::Relaxed);
+            return Err(error);
+        }
+        if attempt >= max_attempts {
+            RETRY_COUNTERS.exhausted.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                operation,
+                attempt,
+                sqlstate = error.sqlstate(),
+                "Giving up on transaction: {}",
+                error
+            );
+            return Err(error);
+        }
+
+        let delay = policy.delay(attempt);
+        RETRY_COUNTERS.retries.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            operation,
+            attempt,
+            sqlstate = error.sqlstate(),
+            delay_ms = delay.as_millis() as u64,
+            "Retrying transaction: {}",
+            error
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+async fn run_attempt<T, F>(manager: &DBManager, policy: &RetryPolicy, f: &mut F) -> Result<T, DbError>
+where
+    F: for<'t> FnMut(&'t Transaction<'t>) -> TransactionFuture<'t, T>,
+{
+    let mut conn = manager.get_conn().await?;
+    let mut builder = conn.build_transaction();
+    if let Some(isolation_level) = policy.isolation_level {
+        builder = builder.isolation_level(isolation_level);
+    }
+    let tx = builder.start().await?;
+
+    // Dropping the transaction on error rolls it back
+    let value = f(&tx).await?;
+
+    tx.commit().await.map_err(|e| {
+        if commit_outcome_unknown(&e) {
+            DbError::CommitUnknown(e)
+        } else {
+            DbError::PostgresError(e)
+        }
+    })?;
+    Ok(value)
+}
+
+/// A connection that broke during COMMIT, with or without a class 08
+/// SQLSTATE, leaves it unknown whether the transaction went through, so
+/// that one is not retried
+fn commit_outcome_unknown(e: &tokio_postgres::Error) -> bool {
+    match e.code() {
+        Some(code) => code.code().starts_with("08"),
+        None => e.is_closed() || is_io_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_manager;
+    use deadpool_postgres::TimeoutType;
+
+    fn pool_timeout() -> DbError {
+        DbError::PoolError(PoolError::Timeout(TimeoutType::Wait))
+    }
+
+    // Connection refused, an error without a SQLSTATE
+    async fn connection_error() -> tokio_postgres::Error {
+        tokio_postgres::connect("host=127.0.0.1 port=1 user=postgres", tokio_postgres::NoTls)
+            .await
+            .err()
+            .expect("nothing listens on port 1")
+    }
+
+    #[test]
+    fn delay_grows_and_stays_below_max_delay() {
+        let policy = RetryPolicy::default();
+        for attempt in [0, 1, 2, 5, 6, 10, 31, 32, 64, u32::MAX] {
+            for _ in 0..20 {
+                assert!(policy.delay(attempt) <= policy.max_delay, "attempt {}", attempt);
+            }
+        }
+
+        // 50ms base: attempt 1 waits 25-50ms, attempt 5 waits 400-800ms
+        for _ in 0..20 {
+            assert!(policy.delay(1) <= policy.base_delay);
+            assert!(policy.delay(5) >= policy.base_delay * 8);
+            assert!(policy.delay(5) > policy.delay(1));
+        }
+    }
+
+    #[test]
+    fn pool_timeout_is_retryable_constraint_errors_are_not() {
+        assert_eq!(pool_timeout().class(), ErrorClass::Retryable);
+
+        let fatal = [
+            DbError::Duplicate {
+                entity: "venue",
+                constraint: "venue_pkey".to_string(),
+            },
+            DbError::InvalidReference {
+                entity: "floorplan",
+                constraint: "floorplan_venue_id_fkey".to_string(),
+            },
+            DbError::NotFound {
+                entity: "venue",
+                id: "v1".to_string(),
+            },
+            DbError::ConfigError("no url".to_string()),
+        ];
+        for error in fatal {
+            assert_eq!(error.class(), ErrorClass::Fatal, "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn lost_connection_is_retryable_unless_during_commit() {
+        let e = connection_error().await;
+        assert_eq!(classify(&e), ErrorClass::Retryable);
+        assert!(DbError::PostgresError(e).is_retryable());
+
+        // The transaction may already be applied, running it again is not safe
+        let e = connection_error().await;
+        assert!(commit_outcome_unknown(&e));
+        let unknown = DbError::CommitUnknown(e);
+        assert_eq!(unknown.class(), ErrorClass::Fatal);
+        assert_eq!(unknown.sqlstate(), "connection");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn classify_by_sqlstate() {
+        let conn = test_manager().get_conn().await.unwrap();
+        // Class and whether the outcome is unknown when COMMIT fails with it
+        let cases = [
+            ("40001", ErrorClass::Retryable, false),
+            ("40P01", ErrorClass::Retryable, false),
+            ("55P03", ErrorClass::Retryable, false),
+            ("08006", ErrorClass::Retryable, true),
+            ("08003", ErrorClass::Retryable, true),
+            ("23505", ErrorClass::Fatal, false),
+            ("42601", ErrorClass::Fatal, false),
+        ];
+        for (code, class, commit_unknown) in cases {
+            let e = conn
+                .batch_execute(&format!(
+                    "DO $$ BEGIN RAISE EXCEPTION 'test' USING ERRCODE = '{}'; END $$",
+                    code
+                ))
+                .await
+                .unwrap_err();
+            assert_eq!(classify(&e), class, "{}", code);
+            assert_eq!(commit_outcome_unknown(&e), commit_unknown, "{}", code);
+        }
+    }
+
+    // The only test that runs transactions, so the counters move by exactly these
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn retry_transaction_updates_counters() {
+        let manager = test_manager();
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let start = retry_metrics();
+
+        // Fails once, then commits
+        let mut calls = 0;
+        let value = retry_transaction(&manager, &policy, "test_recovered", |tx| {
+            calls += 1;
+            let fail = calls == 1;
+            Box::pin(async move {
+                if fail {
+                    return Err(pool_timeout());
+                }
+                let row = tx.query_one("SELECT 1::INT AS one", &[]).await?;
+                Ok(row.get::<_, i32>("one"))
+            })
+        })
+        .await
+        .unwrap();
+        assert_eq!((value, calls), (1, 2));
+
+        // Retryable on every attempt
+        let mut calls = 0;
+        let error = retry_transaction(&manager, &policy, "test_exhausted", |_| {
+            calls += 1;
+            Box::pin(async move { Err::<(), _>(pool_timeout()) })
+        })
+        .await
+        .unwrap_err();
+        assert!(error.is_retryable());
+        assert_eq!(calls, 2);
+
+        // Fatal on the first attempt
+        let mut calls = 0;
+        let error = retry_transaction(&manager, &policy, "test_fatal", |_| {
+            calls += 1;
+            Box::pin(async move {
+                Err::<(), _>(DbError::Duplicate {
+                    entity: "venue",
+                    constraint: "venue_pkey".to_string(),
+                })
+            })
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(error, DbError::Duplicate { .. }));
+        assert_eq!(calls, 1);
+
+        let end = retry_metrics();
+        assert_eq!(end.transactions - start.transactions, 3);
+        assert_eq!(end.retries - start.retries, 2);
+        assert_eq!(end.recovered - start.recovered, 1);
+        assert_eq!(end.exhausted - start.exhausted, 1);
+        assert_eq!(end.fatal - start.fatal, 1);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{execute_cached, query_cached, query_one_cached, DbError, FromRow};
+use deadpool_postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+
+const ENTITY: &str = "venue";
+
+const SELECT: &str = "SELECT doc_id, org_id, title FROM venue";
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, FromRow)]
+pub struct Venue {
+    pub doc_id: String,
+    pub org_id: String,
+    pub title: String,
+}
+
+// venue_product references products by doc_id
+#[derive(FromRow)]
+struct VenueProduct {
+    doc_id: String,
+}
+
+pub struct VenueRepository<'a, C: GenericClient> {
+    db: &'a C,
+}
+
+impl<'a, C: GenericClient> VenueRepository<'a, C> {
+    pub fn new(db: &'a C) -> Self {
+        Self { db }
+    }
+
+    pub async fn get(&self, org_id: &str, doc_id: &str) -> Result<Venue, DbError> {
+        query_one_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            &format!("{} WHERE doc_id = $1 AND org_id = $2", SELECT),
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+
+    pub async fn list(&self, org_id: &str) -> Result<Vec<Venue>, DbError> {
+        query_cached(
+            self.db,
+            ENTITY,
+            &format!("{} WHERE org_id = $1 ORDER BY title, doc_id", SELECT),
+            &[&org_id],
+        )
+        .await
+    }
+
+    /// Products assigned to the venue through venue_product
+    pub async fn product_ids(&self, org_id: &str, doc_id: &str) -> Result<Vec<String>, DbError> {
+        let rows: Vec<VenueProduct> = query_cached(
+            self.db,
+            ENTITY,
+            "SELECT p.doc_id
+            FROM venue_product vp
+            JOIN product p ON p.doc_id = vp.product_id
+            WHERE vp.venue_id = $1 AND p.org_id = $2 AND p.is_deleted = false
+            ORDER BY p.doc_id",
+            &[&doc_id, &org_id],
+        )
+        .await?;
+        Ok(rows.into_iter().map(|row| row.doc_id).collect())
+    }
+
+    pub async fn insert(&self, venue: &Venue) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            &venue.doc_id,
+            "INSERT INTO venue (doc_id, org_id, title) VALUES ($1, $2, $3)",
+            &[&venue.doc_id, &venue.org_id, &venue.title],
+        )
+        .await
+    }
+
+    pub async fn update(&self, venue: &Venue) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            &venue.doc_id,
+            "UPDATE venue SET title = $3 WHERE doc_id = $1 AND org_id = $2",
+            &[&venue.doc_id, &venue.org_id, &venue.title],
+        )
+        .await
+    }
+
+    /// Fails with InvalidReference while floorplans still point at the venue
+    pub async fn delete(&self, org_id: &str, doc_id: &str) -> Result<(), DbError> {
+        execute_cached(
+            self.db,
+            ENTITY,
+            doc_id,
+            "DELETE FROM venue WHERE doc_id = $1 AND org_id = $2",
+            &[&doc_id, &org_id],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::repository_conn;
+
+    fn venue(doc_id: &str) -> Venue {
+        Venue {
+            doc_id: doc_id.to_string(),
+            org_id: "org-1".to_string(),
+            title: "Main hall".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn insert_get_update_delete() {
+        let conn = repository_conn().await;
+        let repo = VenueRepository::new(&conn);
+
+        let mut hall = venue("v1");
+        repo.insert(&hall).await.unwrap();
+        assert_eq!(repo.get("org-1", "v1").await.unwrap(), hall);
+
+        let error = repo.insert(&hall).await.unwrap_err();
+        assert!(matches!(error, DbError::Duplicate { entity: "venue", .. }), "{}", error);
+
+        let error = repo.get("org-2", "v1").await.unwrap_err();
+        assert!(matches!(error, DbError::NotFound { entity: "venue", .. }), "{}", error);
+
+        hall.title = "Side hall".to_string();
+        repo.update(&hall).await.unwrap();
+        assert_eq!(repo.list("org-1").await.unwrap(), vec![hall.clone()]);
+
+        repo.delete("org-1", "v1").await.unwrap();
+        assert!(matches!(repo.get("org-1", "v1").await, Err(DbError::NotFound { .. })));
+        assert!(matches!(repo.update(&hall).await, Err(DbError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn delete_with_floorplans_is_invalid_reference() {
+        let conn = repository_conn().await;
+        let repo = VenueRepository::new(&conn);
+        repo.insert(&venue("v1")).await.unwrap();
+        conn.batch_execute(
+            "INSERT INTO floorplan (doc_id, org_id, venue_id, title, no_rooms)
+            VALUES ('f1', 'org-1', 'v1', 'Ground floor', 3)",
+        )
+        .await
+        .unwrap();
+
+        let error = repo.delete("org-1", "v1").await.unwrap_err();
+        assert!(matches!(error, DbError::InvalidReference { entity: "venue", .. }), "{}", error);
+    }
+}
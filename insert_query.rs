@@ -1,37 +1,322 @@
-fn create_insert_from_create_table(create_table_sql: &str) -> Option<String> {
-    // Make sure it's case-insensitive
-    let sql = create_table_sql.to_lowercase();
-    
-    // Find table name
-    let table_start = sql.find("table if not exists")?;
-    let rest = &sql[table_start + "table if not exists".len()..];
-    let mut parts = rest.trim().splitn(2, '(');
-    
-    let table_name = parts.next()?.trim();
-    let fields_part = parts.next()?.trim();
-    
-    // Remove the trailing ')' if it exists
-    let fields_part = fields_part.strip_suffix(')')?.trim();
-    
-    // Split fields
-    let fields: Vec<&str> = fields_part
-        .split(',')
-        .map(|f| f.trim().split_whitespace().next().unwrap_or(""))
-        .filter(|&f| !f.is_empty())
+use sqlparser::ast::{ColumnOption, DataType, Expr, Statement, TableConstraint};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+
+/// Table read from a CREATE TABLE statement. Names keep their case and quotes
+/// exactly as written so they can be pasted back into SQL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDef {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    // Primary key columns, from the column or from a PRIMARY KEY constraint
+    pub primary_key: Vec<String>,
+    // First UNIQUE column set, used as the ON CONFLICT target when the key is generated
+    pub unique: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    // Type as written, e.g. "BIGSERIAL", "VARCHAR(128)", "TEXT[]"
+    pub data_type: String,
+    // No NOT NULL and not part of the primary key
+    pub nullable: bool,
+    // SERIAL types, identity, GENERATED ... AS (expr) or DEFAULT nextval(...)
+    pub is_generated: bool,
+    // DEFAULT expression as written, None for generated columns
+    pub default: Option<String>,
+}
+
+impl TableDef {
+    /// Columns a caller supplies values for
+    fn writable_columns(&self) -> Vec<&str> {
+        self.columns
+            .iter()
+            .filter(|c| !c.is_generated)
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+
+    fn is_generated(&self, column: &str) -> bool {
+        self.columns
+            .iter()
+            .any(|c| c.name == column && c.is_generated)
+    }
+}
+
+/// Every CREATE TABLE in the script, other statements are ignored
+pub fn parse_create_tables(sql: &str) -> Result<Vec<TableDef>, String> {
+    let statements = Parser::parse_sql(&PostgreSqlDialect {}, sql).map_err(|e| e.to_string())?;
+
+    let mut tables = Vec::new();
+    for statement in statements {
+        let Statement::CreateTable(create) = statement else {
+            continue;
+        };
+
+        let mut primary_key = Vec::new();
+        let mut unique = None;
+        let mut columns = Vec::new();
+
+        for column in create.columns.iter() {
+            let name = column.name.to_string();
+            let mut is_generated = is_serial(&column.data_type);
+            let mut nullable = true;
+            let mut default = None;
+
+            for option in column.options.iter() {
+                match &option.option {
+                    ColumnOption::Unique { is_primary: true, .. } => {
+                        primary_key.push(name.clone());
+                        nullable = false;
+                    }
+                    ColumnOption::NotNull => nullable = false,
+                    ColumnOption::Unique { is_primary: false, .. } if unique.is_none() => {
+                        unique = Some(vec![name.clone()]);
+                    }
+                    ColumnOption::Generated { .. } => is_generated = true,
+                    ColumnOption::Default(expr) if is_nextval(expr) => is_generated = true,
+                    ColumnOption::Default(expr) => default = Some(expr.to_string()),
+                    _ => {}
+                }
+            }
+            columns.push(ColumnDef {
+                name,
+                data_type: column.data_type.to_string(),
+                nullable,
+                is_generated,
+                default: default.filter(|_| !is_generated),
+            });
+        }
+
+        for constraint in create.constraints.iter() {
+            match constraint {
+                TableConstraint::PrimaryKey { columns: keys, .. } => {
+                    primary_key = keys.iter().map(|c| c.to_string()).collect();
+                    for column in columns.iter_mut().filter(|c| primary_key.contains(&c.name)) {
+                        column.nullable = false;
+                    }
+                }
+                TableConstraint::Unique { columns, .. } if unique.is_none() => {
+                    unique = Some(columns.iter().map(|c| c.to_string()).collect());
+                }
+                _ => {}
+            }
+        }
+
+        tables.push(TableDef {
+            name: create.name.to_string(),
+            columns,
+            primary_key,
+            unique,
+        });
+    }
+    Ok(tables)
+}
+
+fn is_serial(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Custom(name, _) => matches!(
+            name.to_string().to_uppercase().as_str(),
+            "SERIAL" | "BIGSERIAL" | "SMALLSERIAL" | "SERIAL2" | "SERIAL4" | "SERIAL8"
+        ),
+        _ => false,
+    }
+}
+
+fn is_nextval(expr: &Expr) -> bool {
+    match expr {
+        Expr::Function(function) => function.name.to_string().eq_ignore_ascii_case("nextval"),
+        _ => false,
+    }
+}
+
+fn placeholders(from: usize, count: usize) -> Vec<String> {
+    (from..from + count).map(|i| format!("${}", i)).collect()
+}
+
+fn first_table(create_table_sql: &str) -> Option<TableDef> {
+    parse_create_tables(create_table_sql).ok()?.into_iter().next()
+}
+
+/// INSERT with one placeholder per writable column. A nullable column with a
+/// DEFAULT gets the default when bound to NULL, the cast keeps the parameter
+/// at the column type instead of the type of the default expression.
+pub fn create_insert_from_create_table(create_table_sql: &str) -> Option<String> {
+    let table = first_table(create_table_sql)?;
+    let columns: Vec<&ColumnDef> = table.columns.iter().filter(|c| !c.is_generated).collect();
+    if columns.is_empty() {
+        return None;
+    }
+
+    let names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+    let values: Vec<String> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| match &c.default {
+            Some(default) if c.nullable => {
+                format!("COALESCE(${}::{}, {})", i + 1, c.data_type, default)
+            }
+            _ => format!("${}", i + 1),
+        })
         .collect();
-    
-    // Generate placeholders: $1, $2, ...
-    let placeholders: Vec<String> = (1..=fields.len())
-        .map(|i| format!("${}", i))
-        .collect();
-    
-    // Form the INSERT statement
-    let insert_sql = format!(
+
+    Some(format!(
         "INSERT INTO {} ({}) VALUES ({});",
-        table_name,
-        fields.join(", "),
-        placeholders.join(", ")
-    );
-    
-    Some(insert_sql)
-}
\ No newline at end of file
+        table.name,
+        names.join(", "),
+        values.join(", ")
+    ))
+}
+
+/// UPDATE of every writable non-key column, keyed by the primary key
+pub fn create_update_from_create_table(create_table_sql: &str) -> Option<String> {
+    let table = first_table(create_table_sql)?;
+    if table.primary_key.is_empty() {
+        return None;
+    }
+
+    let columns: Vec<&str> = table
+        .writable_columns()
+        .into_iter()
+        .filter(|c| !table.primary_key.iter().any(|k| k == c))
+        .collect();
+    if columns.is_empty() {
+        return None;
+    }
+
+    let set: Vec<String> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| format!("{} = ${}", c, i + 1))
+        .collect();
+    let keys: Vec<String> = table
+        .primary_key
+        .iter()
+        .enumerate()
+        .map(|(i, k)| format!("{} = ${}", k, columns.len() + i + 1))
+        .collect();
+
+    Some(format!(
+        "UPDATE {} SET {} WHERE {};",
+        table.name,
+        set.join(", "),
+        keys.join(" AND ")
+    ))
+}
+
+/// INSERT ... ON CONFLICT DO UPDATE. The conflict target is the primary key when
+/// it is supplied by the caller, otherwise the first UNIQUE column set.
+pub fn create_upsert_from_create_table(create_table_sql: &str) -> Option<String> {
+    let table = first_table(create_table_sql)?;
+    let columns = table.writable_columns();
+
+    let key_is_writable = !table.primary_key.is_empty()
+        && table.primary_key.iter().all(|k| !table.is_generated(k));
+    let target = if key_is_writable {
+        table.primary_key.clone()
+    } else {
+        table.unique.clone()?
+    };
+
+    let updates: Vec<String> = columns
+        .iter()
+        .filter(|c| !target.iter().any(|k| k == *c))
+        .map(|c| format!("{} = EXCLUDED.{}", c, c))
+        .collect();
+    let action = if updates.is_empty() {
+        "DO NOTHING".to_string()
+    } else {
+        format!("DO UPDATE SET {}", updates.join(", "))
+    };
+
+    Some(format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({}) {};",
+        table.name,
+        columns.join(", "),
+        placeholders(1, columns.len()).join(", "),
+        target.join(", "),
+        action
+    ))
+}
+
+/// SELECT of every column, keyed by the primary key when there is one
+pub fn create_select_from_create_table(create_table_sql: &str) -> Option<String> {
+    let table = first_table(create_table_sql)?;
+    let columns: Vec<&str> = table.columns.iter().map(|c| c.name.as_str()).collect();
+    if columns.is_empty() {
+        return None;
+    }
+
+    let mut sql = format!("SELECT {} FROM {}", columns.join(", "), table.name);
+    if !table.primary_key.is_empty() {
+        let keys: Vec<String> = table
+            .primary_key
+            .iter()
+            .enumerate()
+            .map(|(i, k)| format!("{} = ${}", k, i + 1))
+            .collect();
+        sql.push_str(&format!(" WHERE {}", keys.join(" AND ")));
+    }
+    sql.push(';');
+    Some(sql)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGENT: &str = "CREATE TABLE IF NOT EXISTS ai_agent (
+        id BIGSERIAL PRIMARY KEY NOT NULL,
+        created_on TIMESTAMP DEFAULT now(),
+        title VARCHAR(128) NOT NULL,
+        price NUMERIC(10,2) NOT NULL,
+        \"SortNo\" SMALLINT NOT NULL,
+        total NUMERIC GENERATED ALWAYS AS (price * 2) STORED,
+        CONSTRAINT ai_agent_title UNIQUE (title),
+        CHECK (price >= 0)
+    );";
+
+    #[test]
+    fn insert_skips_generated_columns_and_constraints() {
+        assert_eq!(
+            create_insert_from_create_table(AGENT).unwrap(),
+            "INSERT INTO ai_agent (created_on, title, price, \"SortNo\") \
+             VALUES (COALESCE($1::TIMESTAMP, now()), $2, $3, $4);"
+        );
+    }
+
+    #[test]
+    fn insert_keeps_not_null_defaults_as_placeholders() {
+        assert_eq!(
+            create_insert_from_create_table(
+                "CREATE TABLE venue (doc_id TEXT PRIMARY KEY, is_deleted BOOLEAN NOT NULL DEFAULT false);"
+            )
+            .unwrap(),
+            "INSERT INTO venue (doc_id, is_deleted) VALUES ($1, $2);"
+        );
+    }
+
+    #[test]
+    fn update_and_select_use_primary_key() {
+        assert_eq!(
+            create_update_from_create_table(AGENT).unwrap(),
+            "UPDATE ai_agent SET created_on = $1, title = $2, price = $3, \"SortNo\" = $4 WHERE id = $5;"
+        );
+        assert_eq!(
+            create_select_from_create_table(AGENT).unwrap(),
+            "SELECT id, created_on, title, price, \"SortNo\", total FROM ai_agent WHERE id = $1;"
+        );
+    }
+
+    #[test]
+    fn upsert_falls_back_to_unique_when_key_is_serial() {
+        assert_eq!(
+            create_upsert_from_create_table(AGENT).unwrap(),
+            "INSERT INTO ai_agent (created_on, title, price, \"SortNo\") VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (title) DO UPDATE SET created_on = EXCLUDED.created_on, \
+             price = EXCLUDED.price, \"SortNo\" = EXCLUDED.\"SortNo\";"
+        );
+    }
+}
+
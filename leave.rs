@@ -0,0 +1,471 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, GenericClient, NoTls};
+
+type AppState = Arc<Mutex<Client>>;
+type LeaveResult<T> = Result<T, (StatusCode, String)>;
+
+fn bad_request<T>(msg: &str) -> LeaveResult<T> {
+    Err((StatusCode::BAD_REQUEST, msg.to_string()))
+}
+
+fn internal_error(e: tokio_postgres::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[derive(Debug, Serialize)]
+struct LeaveType {
+    id: i64,
+    title: String,
+    code: String,
+    days_per_year: f64,
+    allow_half_day: bool,
+    is_paid: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LeaveBalance {
+    leave_type_id: i64,
+    title: String,
+    year: i32,
+    allotted: f64,
+    used: f64,
+    pending: f64,
+    available: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct LeaveRequest {
+    id: i64,
+    employee_id: i64,
+    leave_type_id: i64,
+    leave_type: String,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+    day_part: String,
+    days: f64,
+    reason: String,
+    status: String,
+    approver_id: Option<i64>,
+    remarks: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApplyLeaveInput {
+    employee_id: i64,
+    leave_type_id: i64,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+    day_part: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DecisionInput {
+    approver_id: i64,
+    remarks: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BalanceQuery {
+    employee_id: i64,
+    year: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestListQuery {
+    employee_id: Option<i64>,
+    approver_id: Option<i64>,
+    status: Option<String>,
+}
+
+const REQUEST_SELECT: &str = "SELECT lr.id, lr.employee_id, lr.leave_type_id, lt.title AS leave_type,
+        lr.from_date, lr.to_date, lr.day_part, lr.days, lr.reason, lr.status,
+        lr.approver_id, lr.remarks
+    FROM leave_request lr
+    JOIN leave_type lt ON lt.id = lr.leave_type_id";
+
+fn to_leave_request(row: &tokio_postgres::Row) -> LeaveRequest {
+    LeaveRequest {
+        id: row.get("id"),
+        employee_id: row.get("employee_id"),
+        leave_type_id: row.get("leave_type_id"),
+        leave_type: row.get("leave_type"),
+        from_date: row.get("from_date"),
+        to_date: row.get("to_date"),
+        day_part: row.get("day_part"),
+        days: row.get("days"),
+        reason: row.get("reason"),
+        status: row.get("status"),
+        approver_id: row.get("approver_id"),
+        remarks: row.get("remarks"),
+    }
+}
+
+/// Working days between the two dates, skipping Sundays and company holidays
+async fn count_leave_days(
+    client: &impl GenericClient,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+    day_part: &str,
+) -> Result<f64, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            "SELECT date FROM holiday WHERE date >= $1 AND date <= $2",
+            &[&from_date, &to_date],
+        )
+        .await?;
+    let holidays: HashSet<NaiveDate> = rows.iter().map(|row| row.get("date")).collect();
+
+    let days = from_date
+        .iter_days()
+        .take_while(|&d| d <= to_date)
+        .filter(|d| d.weekday() != Weekday::Sun && !holidays.contains(d))
+        .count() as f64;
+
+    if day_part == "full" {
+        Ok(days)
+    } else {
+        Ok(days / 2.0)
+    }
+}
+
+async fn list_leave_types(State(state): State<AppState>) -> LeaveResult<Json<Vec<LeaveType>>> {
+    let client = state.lock().await;
+    let rows = client
+        .query(
+            "SELECT id, title, code, days_per_year, allow_half_day, is_paid
+            FROM leave_type WHERE is_active = true ORDER BY title",
+            &[],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(
+        rows.iter()
+            .map(|row| LeaveType {
+                id: row.get("id"),
+                title: row.get("title"),
+                code: row.get("code"),
+                days_per_year: row.get("days_per_year"),
+                allow_half_day: row.get("allow_half_day"),
+                is_paid: row.get("is_paid"),
+            })
+            .collect(),
+    ))
+}
+
+async fn get_leave_balance(
+    State(state): State<AppState>,
+    Query(input): Query<BalanceQuery>,
+) -> LeaveResult<Json<Vec<LeaveBalance>>> {
+    let year = input.year.unwrap_or_else(|| chrono::Local::now().year());
+    let client = state.lock().await;
+    let rows = client
+        .query(
+            "SELECT lb.leave_type_id, lt.title, lb.year, lb.allotted, lb.used,
+                COALESCE((
+                    SELECT SUM(lr.days) FROM leave_request lr
+                    WHERE lr.employee_id = lb.employee_id
+                        AND lr.leave_type_id = lb.leave_type_id
+                        AND lr.status = 'pending'
+                        AND EXTRACT(YEAR FROM lr.from_date)::INT = lb.year
+                ), 0) AS pending
+            FROM leave_balance lb
+            JOIN leave_type lt ON lt.id = lb.leave_type_id
+            WHERE lb.employee_id = $1 AND lb.year = $2
+            ORDER BY lt.title",
+            &[&input.employee_id, &year],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(
+        rows.iter()
+            .map(|row| {
+                let allotted: f64 = row.get("allotted");
+                let used: f64 = row.get("used");
+                let pending: f64 = row.get("pending");
+                LeaveBalance {
+                    leave_type_id: row.get("leave_type_id"),
+                    title: row.get("title"),
+                    year: row.get("year"),
+                    allotted,
+                    used,
+                    pending,
+                    available: allotted - used - pending,
+                }
+            })
+            .collect(),
+    ))
+}
+
+async fn list_leave_requests(
+    State(state): State<AppState>,
+    Query(input): Query<RequestListQuery>,
+) -> LeaveResult<Json<Vec<LeaveRequest>>> {
+    let client = state.lock().await;
+    let rows = client
+        .query(
+            &format!(
+                "{} LEFT JOIN employee e ON e.id = lr.employee_id
+                WHERE ($1::BIGINT IS NULL OR lr.employee_id = $1)
+                    AND ($2::BIGINT IS NULL OR e.manager_id = $2)
+                    AND ($3::TEXT IS NULL OR lr.status = $3)
+                ORDER BY lr.from_date DESC, lr.id DESC",
+                REQUEST_SELECT
+            ),
+            &[&input.employee_id, &input.approver_id, &input.status],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(rows.iter().map(to_leave_request).collect()))
+}
+
+async fn apply_leave(
+    State(state): State<AppState>,
+    Json(input): Json<ApplyLeaveInput>,
+) -> LeaveResult<Json<LeaveRequest>> {
+    let day_part = input.day_part.unwrap_or_else(|| "full".to_string());
+    if !matches!(day_part.as_str(), "full" | "first-half" | "second-half") {
+        return bad_request("Invalid day part");
+    }
+    if input.from_date > input.to_date {
+        return bad_request("From date must be before to date");
+    }
+    if day_part != "full" && input.from_date != input.to_date {
+        return bad_request("Half day leave must be for a single day");
+    }
+    if input.from_date.year() != input.to_date.year() {
+        return bad_request("Leave cannot span two years, apply separately");
+    }
+
+    let client = state.lock().await;
+
+    let leave_type = client
+        .query_opt(
+            "SELECT allow_half_day, is_paid FROM leave_type WHERE id = $1 AND is_active = true",
+            &[&input.leave_type_id],
+        )
+        .await
+        .map_err(internal_error)?;
+    let Some(leave_type) = leave_type else {
+        return bad_request("Invalid leave type");
+    };
+    if day_part != "full" && !leave_type.get::<_, bool>("allow_half_day") {
+        return bad_request("Half day is not allowed for this leave type");
+    }
+
+    let days = count_leave_days(&*client, input.from_date, input.to_date, &day_part)
+        .await
+        .map_err(internal_error)?;
+    if days == 0.0 {
+        return bad_request("Selected dates are holidays");
+    }
+
+    let overlap = client
+        .query_opt(
+            "SELECT id FROM leave_request
+            WHERE employee_id = $1
+                AND status IN ('pending', 'approved')
+                AND from_date <= $3 AND to_date >= $2
+            LIMIT 1",
+            &[&input.employee_id, &input.from_date, &input.to_date],
+        )
+        .await
+        .map_err(internal_error)?;
+    if overlap.is_some() {
+        return bad_request("Leave already applied for these dates");
+    }
+
+    // Paid leave must fit within what is left after approved and pending requests
+    if leave_type.get::<_, bool>("is_paid") {
+        let row = client
+            .query_opt(
+                "SELECT lb.allotted - lb.used - COALESCE((
+                        SELECT SUM(lr.days) FROM leave_request lr
+                        WHERE lr.employee_id = lb.employee_id
+                            AND lr.leave_type_id = lb.leave_type_id
+                            AND lr.status = 'pending'
+                            AND EXTRACT(YEAR FROM lr.from_date)::INT = lb.year
+                    ), 0) AS available
+                FROM leave_balance lb
+                WHERE lb.employee_id = $1 AND lb.leave_type_id = $2 AND lb.year = $3",
+                &[&input.employee_id, &input.leave_type_id, &input.from_date.year()],
+            )
+            .await
+            .map_err(internal_error)?;
+        let available: f64 = row.map(|r| r.get("available")).unwrap_or(0.0);
+        if available < days {
+            return bad_request("Insufficient leave balance");
+        }
+    }
+
+    let row = client
+        .query_one(
+            "INSERT INTO leave_request (employee_id, leave_type_id, from_date, to_date, day_part, days, reason)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id",
+            &[
+                &input.employee_id,
+                &input.leave_type_id,
+                &input.from_date,
+                &input.to_date,
+                &day_part,
+                &days,
+                &input.reason.unwrap_or_default(),
+            ],
+        )
+        .await
+        .map_err(internal_error)?;
+    let id: i64 = row.get("id");
+
+    let row = client
+        .query_one(&format!("{} WHERE lr.id = $1", REQUEST_SELECT), &[&id])
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(to_leave_request(&row)))
+}
+
+async fn decide_leave(
+    state: AppState,
+    id: i64,
+    input: DecisionInput,
+    approve: bool,
+) -> LeaveResult<Json<LeaveRequest>> {
+    let mut client = state.lock().await;
+    let transaction = client.transaction().await.map_err(internal_error)?;
+
+    let row = transaction
+        .query_opt(
+            "SELECT lr.employee_id, lr.leave_type_id, lr.from_date, lr.days, lr.status,
+                lt.is_paid, e.manager_id
+            FROM leave_request lr
+            JOIN leave_type lt ON lt.id = lr.leave_type_id
+            JOIN employee e ON e.id = lr.employee_id
+            WHERE lr.id = $1
+            FOR UPDATE OF lr",
+            &[&id],
+        )
+        .await
+        .map_err(internal_error)?;
+    let Some(row) = row else {
+        return Err((StatusCode::NOT_FOUND, "Leave request not found".to_string()));
+    };
+
+    if row.get::<_, Option<i64>>("manager_id") != Some(input.approver_id) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Only the reporting manager can approve or reject".to_string(),
+        ));
+    }
+    if row.get::<_, String>("status") != "pending" {
+        return bad_request("Leave request is already processed");
+    }
+
+    if approve && row.get::<_, bool>("is_paid") {
+        let from_date: NaiveDate = row.get("from_date");
+        let days: f64 = row.get("days");
+        let updated = transaction
+            .execute(
+                "UPDATE leave_balance SET used = used + $4, modified_on = now()
+                WHERE employee_id = $1 AND leave_type_id = $2 AND year = $3
+                    AND allotted - used >= $4",
+                &[
+                    &row.get::<_, i64>("employee_id"),
+                    &row.get::<_, i64>("leave_type_id"),
+                    &from_date.year(),
+                    &days,
+                ],
+            )
+            .await
+            .map_err(internal_error)?;
+        if updated == 0 {
+            return bad_request("Insufficient leave balance");
+        }
+    }
+
+    let status = if approve { "approved" } else { "rejected" };
+    transaction
+        .execute(
+            "UPDATE leave_request
+            SET status = $2, approver_id = $3, remarks = $4, decided_on = now(), modified_on = now()
+            WHERE id = $1",
+            &[&id, &status, &input.approver_id, &input.remarks],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    let row = transaction
+        .query_one(&format!("{} WHERE lr.id = $1", REQUEST_SELECT), &[&id])
+        .await
+        .map_err(internal_error)?;
+    transaction.commit().await.map_err(internal_error)?;
+
+    Ok(Json(to_leave_request(&row)))
+}
+
+async fn approve_leave(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<DecisionInput>,
+) -> LeaveResult<Json<LeaveRequest>> {
+    decide_leave(state, id, input, true).await
+}
+
+async fn reject_leave(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<DecisionInput>,
+) -> LeaveResult<Json<LeaveRequest>> {
+    decide_leave(state, id, input, false).await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), tokio_postgres::Error> {
+    let (client, connection) = tokio_postgres::connect(
+        "host=localhost user=postgres dbname=postgres password=password",
+        NoTls,
+    )
+    .await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+
+    let app = Router::new()
+        .route("/leave/types", get(list_leave_types))
+        .route("/leave/balance", get(get_leave_balance))
+        .route("/leave/requests", get(list_leave_requests).post(apply_leave))
+        .route("/leave/requests/:id/approve", post(approve_leave))
+        .route("/leave/requests/:id/reject", post(reject_leave))
+        .with_state(Arc::new(Mutex::new(client)));
+
+    println!("🚀 Server running at http://127.0.0.1:3000/leave/types");
+    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+
+    Ok(())
+}
+
+
+[dependencies]
+axum = "0.6"
+tokio = { version = "1", features = ["full"] }
+tokio-postgres = { version = "0.7", features = ["with-chrono-0_4"] }
+chrono = { version = "0.4", features = ["serde"] }
+serde = { version = "1", features = ["derive"] }
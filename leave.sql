@@ -0,0 +1,66 @@
+CREATE TABLE IF NOT EXISTS leave_type (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    created_on TIMESTAMP DEFAULT now(),
+    modified_on TIMESTAMP DEFAULT now(),
+    title VARCHAR(64) NOT NULL,
+    code VARCHAR(16) NOT NULL UNIQUE,
+    days_per_year DOUBLE PRECISION NOT NULL DEFAULT 0,
+    allow_half_day BOOLEAN NOT NULL DEFAULT true,
+    is_paid BOOLEAN NOT NULL DEFAULT true,
+    is_active BOOLEAN NOT NULL DEFAULT true
+);
+
+CREATE TABLE IF NOT EXISTS leave_balance (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    created_on TIMESTAMP DEFAULT now(),
+    modified_on TIMESTAMP DEFAULT now(),
+    employee_id BIGINT NOT NULL REFERENCES employee(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    leave_type_id BIGINT NOT NULL REFERENCES leave_type(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    year INT NOT NULL,
+    allotted DOUBLE PRECISION NOT NULL DEFAULT 0,
+    used DOUBLE PRECISION NOT NULL DEFAULT 0,
+    UNIQUE (employee_id, leave_type_id, year)
+);
+
+-- day_part: 'full', 'first-half' or 'second-half' (half days only when from_date = to_date)
+-- status: 'pending', 'approved' or 'rejected'
+CREATE TABLE IF NOT EXISTS leave_request (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    created_on TIMESTAMP DEFAULT now(),
+    modified_on TIMESTAMP DEFAULT now(),
+    employee_id BIGINT NOT NULL REFERENCES employee(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    leave_type_id BIGINT NOT NULL REFERENCES leave_type(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    from_date DATE NOT NULL,
+    to_date DATE NOT NULL,
+    day_part VARCHAR(16) NOT NULL DEFAULT 'full',
+    days DOUBLE PRECISION NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    approver_id BIGINT REFERENCES employee(id),
+    decided_on TIMESTAMP,
+    remarks TEXT,
+    CHECK (from_date <= to_date),
+    CHECK (day_part = 'full' OR from_date = to_date)
+);
+
+CREATE INDEX IF NOT EXISTS leave_request_employee_dates
+    ON leave_request (employee_id, from_date, to_date);
+
+-- Reporting manager, the only one allowed to approve or reject
+ALTER TABLE employee ADD COLUMN IF NOT EXISTS manager_id BIGINT REFERENCES employee(id);
+
+
+INSERT INTO leave_type (title, code, days_per_year, allow_half_day, is_paid)
+VALUES
+    ('Casual Leave', 'CL', 12, true, true),
+    ('Sick Leave', 'SL', 10, true, true),
+    ('Earned Leave', 'EL', 15, false, true),
+    ('Loss of Pay', 'LOP', 0, true, false);
+
+-- Allot the yearly quota to every active employee
+INSERT INTO leave_balance (employee_id, leave_type_id, year, allotted)
+SELECT e.id, lt.id, 2024, lt.days_per_year
+FROM employee e
+CROSS JOIN leave_type lt
+WHERE e.is_active = true
+ON CONFLICT (employee_id, leave_type_id, year) DO NOTHING;
@@ -1,25 +1,29 @@
+let mut builder = ProductFilterBuilder::new(org_id);
+
 if let Some(category) = &filters.category {
+    // The listing UI sends "NONE" for uncategorised, turn it into the filter variant here
     let has_none = category.values.iter().any(|c| c == "NONE");
-    let other_ids: Vec<_> = category.values.iter().filter(|c| *c != "NONE").collect();
+    let other_ids: Vec<String> = category
+        .values
+        .iter()
+        .filter(|c| *c != "NONE")
+        .cloned()
+        .collect();
 
+    let mut any = Vec::new();
     if has_none {
-        if other_ids.is_empty() {
-            // Only NONE → empty categories
-            where_conditions.push(format!("p.categories = ${}", params.len() + 1));
-            params.push(&Vec::<String>::new());
-        } else {
-            // NONE + other IDs → empty OR overlap
-            where_conditions.push(format!(
-                "(p.categories = ${} OR p.categories && ${})",
-                params.len() + 1,
-                params.len() + 2
-            ));
-            params.push(&Vec::<String>::new());   // for empty []
-            params.push(&other_ids);              // for other categories
-        }
-    } else {
-        // Normal category filter
-        where_conditions.push(format!("p.categories && ${}", params.len() + 1));
-        params.push(&category.values);
+        any.push(ProductFilter::Uncategorised);
+    }
+    if !other_ids.is_empty() {
+        any.push(ProductFilter::HasAny(ProductListColumn::Categories, other_ids));
     }
-}
\ No newline at end of file
+    builder.filter(&ProductFilter::Any(any));
+}
+
+let query = format!(
+    "SELECT p.doc_id, p.title, p.categories FROM product p
+    WHERE {} AND p.org_id = $1 AND p.is_deleted = 'f' AND p.is_archive = 'f'
+    ORDER BY p.created_on",
+    builder.where_clause()
+);
+let rows = db.query(&query, &builder.params()).await?;
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use chrono::NaiveDate;
 use tokio_postgres::{NoTls, Error};
 
 #[tokio::main] 
@@ -16,18 +16,11 @@ async fn main() -> Result<(), Error> {
             eprintln!("connection error: {}", e);
         }
     });
-        
-    fn convert_naive_date_to_datetime(naive_date: NaiveDate) -> DateTime<Utc> {
-        DateTime::<Utc>::from_naive_utc_and_offset(naive_date.and_hms_opt(0, 0, 0).unwrap(), Utc)
-    }
 
-    let employee_id = 1002;
+    let employee_id: i64 = 1002;
     let start = NaiveDate::from_ymd_opt(2024,03,1).unwrap();
     let end = NaiveDate::from_ymd_opt(2024,03,29).unwrap();
 
-    let start_date_db = convert_naive_date_to_datetime(NaiveDate::from_ymd_opt(2024,03,1).unwrap());
-    let end_date_db = convert_naive_date_to_datetime(NaiveDate::from_ymd_opt(2024,03,29).unwrap());
-
     #[derive(Debug)]
     struct StatusLogEmployeeItem{
         date: NaiveDate,
@@ -38,68 +31,59 @@ async fn main() -> Result<(), Error> {
         day_type:  Option<String>,
     }
 
-    // Perform a simple query to select some data from a table
+    // Expand the calendar in SQL and LEFT JOIN the per-day logs, so every date
+    // comes back as exactly one row in a single round trip
     let rows = client
         .query("
-        SELECT 
-        DATE(created_on) AS created_date,
-        SUBSTRING(created_on::TEXT, 12, 8) AS created_time,
-        SUBSTRING(modified_on::TEXT, 12, 8) AS modified_time,
-        employee_id, 
-        CAST(SUM(CASE WHEN status = 'break-in' THEN time_taken ELSE 0 END) AS INT) AS break_time, 
-        CAST(SUM(CASE WHEN status = 'Lunch-in' THEN time_taken ELSE 0 END) AS INT) AS lunch_time 
-    FROM 
-        employee_status_log 
-    WHERE 
-        created_on >= $1 
-        AND created_on <= $2 
-      GROUP BY 
-      DATE(created_on), 
-      SUBSTRING(created_on::TEXT, 12, 8),
-      SUBSTRING(modified_on::TEXT, 12, 8),
-      employee_id
-      ", &[&start_date_db,&end_date_db])
+        WITH days AS (
+            SELECT d::DATE AS created_date
+            FROM generate_series($2::DATE, $3::DATE, INTERVAL '1 day') AS d
+        ),
+        logs AS (
+            SELECT
+                DATE(created_on) AS created_date,
+                MIN(SUBSTRING(created_on::TEXT, 12, 8)) AS created_time,
+                MAX(SUBSTRING(modified_on::TEXT, 12, 8)) AS modified_time,
+                CAST(SUM(CASE WHEN status = 'break-in' THEN time_taken ELSE 0 END) AS INT) AS break_time,
+                CAST(SUM(CASE WHEN status = 'lunch-in' THEN time_taken ELSE 0 END) AS INT) AS lunch_time
+            FROM
+                employee_status_log
+            WHERE
+                employee_id = $1
+                AND DATE(created_on) >= $2
+                AND DATE(created_on) <= $3
+            GROUP BY
+                DATE(created_on)
+        )
+        SELECT
+            days.created_date,
+            COALESCE(logs.created_time, '') AS created_time,
+            COALESCE(logs.modified_time, '') AS modified_time,
+            COALESCE(logs.break_time, 0) AS break_time,
+            COALESCE(logs.lunch_time, 0) AS lunch_time,
+            CASE
+                -- Mark Sundays as holidays
+                WHEN EXTRACT(ISODOW FROM days.created_date) = 7 THEN 'Holiday'
+                WHEN logs.created_date IS NULL THEN 'Leave'
+                ELSE 'Present'
+            END AS day_type
+        FROM days
+        LEFT JOIN logs ON logs.created_date = days.created_date
+        ORDER BY days.created_date
+      ", &[&employee_id, &start, &end])
         .await?;
 
-    // Iterate over the rows and print them
-
-    let mut items: Vec<StatusLogEmployeeItem> =  vec![];
-
-    for date in start.iter_days().take_while(|&d| d <= end) {
-        let mut found = false;
-        for row in &rows {
-            if row.get::<_, NaiveDate>("created_date") == date {
-                found = true;
-                items.push(StatusLogEmployeeItem {
-                    date: row.get("created_date"),
-                    in_time: row.get("created_time"),
-                    out_time: row.get("modified_time"),
-                    break_time: row.get("break_time"),
-                    lunch_time: row.get("lunch_time"),
-                    day_type: Some("Present".to_string()),
-                });
-                break;
-            }
-        }
-        if !found {
-            // If no data for this date, consider it as leave
-            items.push(StatusLogEmployeeItem {
-                date,
-                in_time: "".to_string(),
-                out_time: "".to_string(),
-                break_time: 0,
-                lunch_time: 0,
-                day_type: Some("Leave".to_string()),
-            });
-        }
-    }
-
-    // Mark Sundays as holidays
-    for item in &mut items {
-        if item.date.weekday() == chrono::Weekday::Sun {
-            item.day_type = Some("Holiday".to_string());
-        }
-    }
+    let items: Vec<StatusLogEmployeeItem> = rows
+        .iter()
+        .map(|row| StatusLogEmployeeItem {
+            date: row.get("created_date"),
+            in_time: row.get("created_time"),
+            out_time: row.get("modified_time"),
+            break_time: row.get("break_time"),
+            lunch_time: row.get("lunch_time"),
+            day_type: row.get("day_type"),
+        })
+        .collect();
 
     // for row in &rows {
     //     let date: NaiveDate = row.get("created_date");
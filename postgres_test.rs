@@ -1,34 +1,37 @@
-use tokio_postgres::{NoTls, Row};
+use tokio_postgres::Row;
+use tokio_postgres::types::{FromSql, Type};
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use from_row_derive::FromRow;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
 use serde::{Serialize, Deserialize};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+#[path = "test_db.rs"]
+#[allow(dead_code)]
+mod test_db;
+
+use test_db::TestDb;
+
+const ITEMS_TABLE: &str =
+    "CREATE TABLE items (id SERIAL PRIMARY KEY, name TEXT NOT NULL, created_on TIMESTAMP DEFAULT now())";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Connect to database
-    let (client, connection) = tokio_postgres::connect(
-        "host=localhost user=postgres password=postgres dbname=postgres",
-        NoTls,
-    ).await?;
+    // Own database, dropped again at the end of main
+    let db = TestDb::builder().sql(ITEMS_TABLE).create().await?;
+    check_items(db.client()).await?;
 
-    // Spawn connection
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            eprintln!("connection error: {}", e);
-        }
-    });
+    println!("All items match successfully!");
 
-    // Prepare table
-    client.execute(
-        "DROP TABLE IF EXISTS items",
-        &[]
-    ).await?;
-    client.execute(
-        "CREATE TABLE items (id SERIAL PRIMARY KEY, name TEXT NOT NULL)",
-        &[]
-    ).await?;
+    Ok(())
+}
 
+async fn check_items(client: &tokio_postgres::Client) -> Result<(), Box<dyn std::error::Error>> {
     // Insert test data
     let item1 = Item { id: 1, name: "Apple".to_string() };
     let item2 = Item { id: 2, name: "Banana".to_string() };
@@ -44,9 +47,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ).await?;
 
     // Now, check in database
-    check_db_insert(&client, &[item1, item2]).await?;
+    check_db_insert(client, &[item1, item2]).await?;
 
-    println!("All items match successfully!");
+    // Update one, delete the other and check both
+    let item1 = Item { id: 1, name: "Green Apple".to_string() };
+    client.execute(
+        "UPDATE items SET name = $2 WHERE id = $1",
+        &[&item1.id, &item1.name]
+    ).await?;
+    check_db_update(client, &[item1]).await?;
+
+    client.execute("DELETE FROM items WHERE id = $1", &[&2i32]).await?;
+    check_db_delete::<Item>(client, &[2]).await?;
+
+    // Typed mapping without going through JSON
+    let rows = client.query("SELECT id, name FROM items ORDER BY id", &[]).await?;
+    let items = rows.iter().map(Item::from_row).collect::<Result<Vec<_>, _>>()?;
+    println!("Read back {} items", items.len());
 
     Ok(())
 }
@@ -55,58 +72,275 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 #[async_trait]
 pub trait DbCheckable: Sized + Serialize + DeserializeOwned {
-    fn id_value(&self) -> &(dyn tokio_postgres::types::ToSql + Sync);
+    type Id: tokio_postgres::types::ToSql + Sync + Serialize + fmt::Debug;
+
+    fn id(&self) -> Self::Id;
     fn table_name() -> &'static str;
     fn id_column() -> &'static str;
+
+    /// Columns set by the database that the expected value cannot know
+    fn ignored_columns() -> &'static [&'static str] {
+        &["created_on", "modified_on"]
+    }
+}
+
+#[derive(Debug)]
+pub struct FieldDiff {
+    pub field: String,
+    pub expected: Value,
+    pub found: Value,
 }
 
+/// Everything that did not match, collected over the whole batch
+#[derive(Debug, Default)]
+pub struct DbCheckReport {
+    pub table: &'static str,
+    // Expected rows that are not in the table
+    pub missing: Vec<Value>,
+    // Rows that should be gone but are still there
+    pub unexpected: Vec<Value>,
+    pub mismatches: Vec<(Value, Vec<FieldDiff>)>,
+}
+
+impl DbCheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for DbCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} check failed", self.table)?;
+        for id in self.missing.iter() {
+            writeln!(f, "  {}: missing", id)?;
+        }
+        for id in self.unexpected.iter() {
+            writeln!(f, "  {}: still present", id)?;
+        }
+        for (id, diffs) in self.mismatches.iter() {
+            for diff in diffs {
+                writeln!(
+                    f,
+                    "  {}.{}: expected {}, found {}",
+                    id, diff.field, diff.expected, diff.found
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DbCheckReport {}
+
+/// Inserted rows must match the items, field by field
 pub async fn check_db_insert<T>(
     client: &tokio_postgres::Client,
     items: &[T],
 ) -> Result<(), Box<dyn std::error::Error>>
 where
-    T: DbCheckable + std::fmt::Debug,
+    T: DbCheckable,
+{
+    check_db_rows(client, items).await
+}
+
+/// Updated rows must hold the new values
+pub async fn check_db_update<T>(
+    client: &tokio_postgres::Client,
+    items: &[T],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    T: DbCheckable,
+{
+    check_db_rows(client, items).await
+}
+
+/// None of the ids may be left in the table
+pub async fn check_db_delete<T>(
+    client: &tokio_postgres::Client,
+    ids: &[T::Id],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    T: DbCheckable,
 {
-    for item in items {
-        let query = format!("SELECT * FROM {} WHERE {} = $1", T::table_name(), T::id_column());
-        let row = client.query_one(&query, &[item.id_value()]).await?;
+    let rows = fetch_rows::<T>(client, ids).await?;
+    let report = DbCheckReport {
+        table: T::table_name(),
+        unexpected: rows.into_values().map(|(id, _)| id).collect(),
+        ..Default::default()
+    };
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(report.into())
+    }
+}
 
-        let db_item: T = deserialize_row(&row)?;
+async fn check_db_rows<T>(
+    client: &tokio_postgres::Client,
+    items: &[T],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    T: DbCheckable,
+{
+    let ids: Vec<T::Id> = items.iter().map(|item| item.id()).collect();
+    let rows = fetch_rows::<T>(client, &ids).await?;
 
-        if serde_json::to_value(item)? != serde_json::to_value(&db_item)? {
-            return Err(format!("Mismatch:\nexpected: {:?}\nfound: {:?}", item, db_item).into());
+    let mut report = DbCheckReport {
+        table: T::table_name(),
+        ..Default::default()
+    };
+    for (item, id) in items.iter().zip(ids.iter()) {
+        let id = serde_json::to_value(id)?;
+        match rows.get(&id.to_string()) {
+            Some((_, row)) => {
+                // Round trip through T so both sides have the same shape and types
+                let found: T = deserialize_row(row)?;
+                let diffs = diff_fields(
+                    &serde_json::to_value(item)?,
+                    &serde_json::to_value(&found)?,
+                    T::ignored_columns(),
+                );
+                if !diffs.is_empty() {
+                    report.mismatches.push((id, diffs));
+                }
+            }
+            None => report.missing.push(id),
         }
     }
-    Ok(())
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(report.into())
+    }
+}
+
+/// One query for the whole batch, rows keyed by their id as JSON text
+async fn fetch_rows<T: DbCheckable>(
+    client: &tokio_postgres::Client,
+    ids: &[T::Id],
+) -> Result<HashMap<String, (Value, Row)>, Box<dyn std::error::Error>> {
+    let query = format!(
+        "SELECT * FROM {} WHERE {} = ANY($1)",
+        T::table_name(),
+        T::id_column()
+    );
+    let rows = client.query(&query, &[&ids]).await?;
+
+    let mut by_id = HashMap::new();
+    for row in rows {
+        let idx = row
+            .columns()
+            .iter()
+            .position(|c| c.name() == T::id_column())
+            .ok_or_else(|| format!("Column {} not in result", T::id_column()))?;
+        let id = column_to_json(&row, idx, row.columns()[idx].type_())?;
+        by_id.insert(id.to_string(), (id, row));
+    }
+    Ok(by_id)
+}
+
+fn diff_fields(expected: &Value, found: &Value, ignored: &[&str]) -> Vec<FieldDiff> {
+    let empty = serde_json::Map::new();
+    let expected = expected.as_object().unwrap_or(&empty);
+    let found = found.as_object().unwrap_or(&empty);
+
+    let mut fields: Vec<&String> = expected.keys().chain(found.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter(|field| !ignored.contains(&field.as_str()))
+        .filter_map(|field| {
+            let expected = expected.get(field).cloned().unwrap_or(Value::Null);
+            let found = found.get(field).cloned().unwrap_or(Value::Null);
+            (expected != found).then(|| FieldDiff {
+                field: field.to_string(),
+                expected,
+                found,
+            })
+        })
+        .collect()
 }
 
+/// Read a row into any struct through serde, one JSON value per column
 fn deserialize_row<T: DeserializeOwned>(row: &Row) -> Result<T, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_value(row_to_json(row)?)?)
+}
+
+/// Row as a JSON object keyed by column name, SQL NULL becomes null
+fn row_to_json(row: &Row) -> Result<Value, Box<dyn std::error::Error>> {
     let mut map = serde_json::Map::new();
+    for (idx, column) in row.columns().iter().enumerate() {
+        map.insert(column.name().to_string(), column_to_json(row, idx, column.type_())?);
+    }
+    Ok(Value::Object(map))
+}
 
-    for column in row.columns() {
-        let name = column.name();
-        let value: Value = match *column.type_() {
-            tokio_postgres::types::Type::INT4 => {
-                let v: i32 = row.get(name);
-                Value::Number(v.into())
-            }
-            tokio_postgres::types::Type::TEXT | tokio_postgres::types::Type::VARCHAR => {
-                let v: String = row.get(name);
-                Value::String(v)
-            }
-            _ => {
-                return Err(format!("Unsupported column type: {}", column.type_()).into());
-            }
-        };
-        map.insert(name.to_string(), value);
+fn column_to_json(row: &Row, idx: usize, ty: &Type) -> Result<Value, Box<dyn std::error::Error>> {
+    // Every column is read as Option so NULL never panics
+    fn get<'a, T: FromSql<'a>>(row: &'a Row, idx: usize) -> Result<Option<T>, tokio_postgres::Error> {
+        row.try_get(idx)
+    }
+    fn json<T: Serialize>(value: Option<T>) -> Result<Value, serde_json::Error> {
+        serde_json::to_value(value)
     }
 
-    Ok(serde_json::from_value(Value::Object(map))?)
+    let value = match *ty {
+        Type::BOOL => json(get::<bool>(row, idx)?)?,
+        Type::INT2 => json(get::<i16>(row, idx)?)?,
+        Type::INT4 => json(get::<i32>(row, idx)?)?,
+        Type::INT8 => json(get::<i64>(row, idx)?)?,
+        Type::OID => json(get::<u32>(row, idx)?)?,
+        // NaN and infinity have no JSON form, serde_json writes them as null
+        Type::FLOAT4 => json(get::<f32>(row, idx)?)?,
+        Type::FLOAT8 => json(get::<f64>(row, idx)?)?,
+        Type::NUMERIC => match get::<Decimal>(row, idx)? {
+            // Whole numbers stay integers so they deserialize into i64 fields
+            Some(v) if v.fract().is_zero() && v.to_i64().is_some() => Value::from(v.to_i64()),
+            Some(v) => json(v.to_f64())?,
+            None => Value::Null,
+        },
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::UNKNOWN => {
+            json(get::<String>(row, idx)?)?
+        }
+        Type::TIMESTAMP => json(get::<NaiveDateTime>(row, idx)?)?,
+        Type::TIMESTAMPTZ => json(get::<DateTime<Utc>>(row, idx)?)?,
+        Type::DATE => json(get::<NaiveDate>(row, idx)?)?,
+        Type::TIME => json(get::<NaiveTime>(row, idx)?)?,
+        Type::UUID => json(get::<uuid::Uuid>(row, idx)?)?,
+        Type::JSON | Type::JSONB => get::<Value>(row, idx)?.unwrap_or(Value::Null),
+        Type::BOOL_ARRAY => json(get::<Vec<Option<bool>>>(row, idx)?)?,
+        Type::INT2_ARRAY => json(get::<Vec<Option<i16>>>(row, idx)?)?,
+        Type::INT4_ARRAY => json(get::<Vec<Option<i32>>>(row, idx)?)?,
+        Type::INT8_ARRAY => json(get::<Vec<Option<i64>>>(row, idx)?)?,
+        Type::FLOAT8_ARRAY => json(get::<Vec<Option<f64>>>(row, idx)?)?,
+        Type::TEXT_ARRAY | Type::VARCHAR_ARRAY | Type::BPCHAR_ARRAY => {
+            json(get::<Vec<Option<String>>>(row, idx)?)?
+        }
+        Type::UUID_ARRAY => json(get::<Vec<Option<uuid::Uuid>>>(row, idx)?)?,
+        Type::JSONB_ARRAY | Type::JSON_ARRAY => json(get::<Vec<Option<Value>>>(row, idx)?)?,
+        _ => {
+            return Err(format!(
+                "Unsupported column type {} for column {}",
+                ty,
+                row.columns()[idx].name()
+            )
+            .into());
+        }
+    };
+    Ok(value)
+}
+
+/// Typed mapping from a row, implemented by #[derive(FromRow)]
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, tokio_postgres::Error>;
 }
 
 // --------------- YOUR STRUCT ---------------
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, FromRow)]
 struct Item {
     id: i32,
     name: String,
@@ -114,8 +348,10 @@ struct Item {
 
 #[async_trait]
 impl DbCheckable for Item {
-    fn id_value(&self) -> &(dyn tokio_postgres::types::ToSql + Sync) {
-        &self.id
+    type Id = i32;
+
+    fn id(&self) -> i32 {
+        self.id
     }
 
     fn table_name() -> &'static str {
@@ -127,6 +363,50 @@ impl DbCheckable for Item {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test gets its own database, so both insert id 1 without clashing
+
+    #[tokio::test]
+    async fn items_insert_update_delete() -> Result<(), Box<dyn std::error::Error>> {
+        let db = TestDb::builder().sql(ITEMS_TABLE).create().await?;
+        check_items(db.client()).await
+    }
+
+    #[tokio::test]
+    async fn seed_file_loads() -> Result<(), Box<dyn std::error::Error>> {
+        let db = TestDb::builder()
+            .sql(
+                "CREATE TABLE floorplan (
+                    doc_id TEXT PRIMARY KEY,
+                    org_id TEXT NOT NULL,
+                    venue_id TEXT NOT NULL,
+                    title TEXT NOT NULL,
+                    no_rooms INT NOT NULL
+                )",
+            )
+            .seed("floor.sql")
+            .create()
+            .await?;
+
+        let row = db
+            .client()
+            .query_one("SELECT count(*) AS total FROM floorplan", &[])
+            .await?;
+        assert_eq!(row.get::<_, i64>("total"), 10);
+
+        // C collation, upper case sorts before lower case
+        let row = db
+            .client()
+            .query_one("SELECT 'B' < 'a' AS c_order", &[])
+            .await?;
+        assert!(row.get::<_, bool>("c_order"));
+        Ok(())
+    }
+}
+
 
 
 
@@ -137,7 +417,11 @@ edition = "2021"
 
 [dependencies]
 tokio = { version = "1", features = ["full"] }
-tokio-postgres = "0.7"
+tokio-postgres = { version = "0.7", features = ["with-serde_json-1", "with-chrono-0_4", "with-uuid-1"] }
 serde = { version = "1.0", features = ["derive"] }
 serde_json = "1.0"
-async-trait = "0.1"
\ No newline at end of file
+async-trait = "0.1"
+chrono = { version = "0.4", features = ["serde"] }
+uuid = { version = "1", features = ["serde"] }
+rust_decimal = { version = "1", features = ["db-tokio-postgres"] }
+from_row_derive = { path = "from_row_derive" }
\ No newline at end of file
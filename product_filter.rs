@@ -0,0 +1,239 @@
+use tokio_postgres::types::ToSql;
+
+/// Array columns of the product table that hold ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductListColumn {
+    Categories,
+    Tags,
+    DietaryOptions,
+    Attributes,
+}
+
+impl ProductListColumn {
+    fn column(&self) -> &'static str {
+        match self {
+            ProductListColumn::Categories => "p.categories",
+            ProductListColumn::Tags => "p.tags",
+            ProductListColumn::DietaryOptions => "p.dietary_options",
+            ProductListColumn::Attributes => "p.attributes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductNumberColumn {
+    Price,
+    CalorieCount,
+}
+
+impl ProductNumberColumn {
+    fn column(&self) -> &'static str {
+        match self {
+            ProductNumberColumn::Price => "p.price",
+            ProductNumberColumn::CalorieCount => "p.calorie_count",
+        }
+    }
+}
+
+/// A condition on the product table (aliased `p`), shared by the product
+/// listing and the smart rule engine
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProductFilter {
+    /// Has at least one of the ids
+    HasAny(ProductListColumn, Vec<String>),
+    /// Has every one of the ids
+    HasAll(ProductListColumn, Vec<String>),
+    /// In the category or any category below it
+    InCategoryTree(String),
+    /// Assigned to at least one of the venues
+    InVenues(Vec<String>),
+    Range {
+        column: ProductNumberColumn,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    HasAlcohol(bool),
+    /// No category at all
+    Uncategorised,
+    /// Not assigned to any venue
+    UnassignedVenue,
+    /// No attribute at all
+    NoAttributes,
+    Not(Box<ProductFilter>),
+    Any(Vec<ProductFilter>),
+    All(Vec<ProductFilter>),
+}
+
+/// Renders product filters into a parameterised WHERE clause.
+/// The org id is always $1 so callers can scope the outer query with it.
+pub struct ProductFilterBuilder {
+    conditions: Vec<String>,
+    params: Vec<Box<dyn ToSql + Sync + Send>>,
+    // Category subtrees come from category_closure instead of a recursive walk
+    category_closure: bool,
+}
+
+impl ProductFilterBuilder {
+    pub fn new(org_id: &str) -> Self {
+        let mut builder = ProductFilterBuilder {
+            conditions: Vec::new(),
+            params: Vec::new(),
+            category_closure: false,
+        };
+        builder.push_param(org_id.to_string());
+        builder
+    }
+
+    /// Set from has_category_closure, the closure table is not there before its migration
+    pub fn category_closure(&mut self, enabled: bool) -> &mut Self {
+        self.category_closure = enabled;
+        self
+    }
+
+    /// Add a param for a condition written by the caller, returns its index
+    pub fn push_param<T: ToSql + Sync + Send + 'static>(&mut self, value: T) -> usize {
+        self.params.push(Box::new(value));
+        self.params.len()
+    }
+
+    /// AND a filter into the clause
+    pub fn filter(&mut self, filter: &ProductFilter) -> &mut Self {
+        let condition = self.render(filter);
+        self.conditions.push(condition);
+        self
+    }
+
+    /// AND a hand-written condition into the clause
+    pub fn condition(&mut self, condition: String) -> &mut Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            "TRUE".to_string()
+        } else {
+            self.conditions.join(" AND ")
+        }
+    }
+
+    pub fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params
+            .iter()
+            .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+            .collect()
+    }
+
+    pub fn into_parts(self) -> (String, Vec<Box<dyn ToSql + Sync + Send>>) {
+        (self.where_clause(), self.params)
+    }
+
+    fn render(&mut self, filter: &ProductFilter) -> String {
+        match filter {
+            ProductFilter::HasAny(column, ids) => {
+                format!("{} && ${}", column.column(), self.push_param(ids.clone()))
+            }
+            ProductFilter::HasAll(column, ids) => {
+                format!("{} @> ${}", column.column(), self.push_param(ids.clone()))
+            }
+            ProductFilter::InCategoryTree(id) => {
+                let idx = self.push_param(id.clone());
+                format!(
+                    "p.categories && {}",
+                    category_subtree_sql(idx, self.category_closure)
+                )
+            }
+            // EXISTS keeps one row per product and negates cleanly inside groups
+            ProductFilter::InVenues(ids) => format!(
+                "EXISTS (SELECT 1 FROM venue_product vp WHERE vp.product_id = p.doc_id AND vp.venue_id = ANY(${}))",
+                self.push_param(ids.clone())
+            ),
+            ProductFilter::Range { column, min, max } => {
+                // Cast so the float params compare against both INT and NUMERIC columns
+                let mut bounds = Vec::new();
+                if let Some(min) = min {
+                    bounds.push(format!("{} >= ${}::FLOAT8", column.column(), self.push_param(*min)));
+                }
+                if let Some(max) = max {
+                    bounds.push(format!("{} <= ${}::FLOAT8", column.column(), self.push_param(*max)));
+                }
+                if bounds.is_empty() {
+                    "TRUE".to_string()
+                } else {
+                    format!("({})", bounds.join(" AND "))
+                }
+            }
+            ProductFilter::HasAlcohol(value) => {
+                format!("p.has_alcohol = ${}", self.push_param(*value))
+            }
+            // NULL and '{}' both mean nothing assigned
+            ProductFilter::Uncategorised => "COALESCE(cardinality(p.categories), 0) = 0".to_string(),
+            ProductFilter::NoAttributes => "COALESCE(cardinality(p.attributes), 0) = 0".to_string(),
+            ProductFilter::UnassignedVenue => {
+                "NOT EXISTS (SELECT 1 FROM venue_product vp WHERE vp.product_id = p.doc_id)".to_string()
+            }
+            ProductFilter::Not(filter) => format!("NOT ({})", self.render(filter)),
+            ProductFilter::Any(filters) => {
+                if filters.is_empty() {
+                    return "FALSE".to_string();
+                }
+                let conditions: Vec<String> = filters.iter().map(|f| self.render(f)).collect();
+                format!("({})", conditions.join(" OR "))
+            }
+            ProductFilter::All(filters) => {
+                if filters.is_empty() {
+                    return "TRUE".to_string();
+                }
+                let conditions: Vec<String> = filters.iter().map(|f| self.render(f)).collect();
+                format!("({})", conditions.join(" AND "))
+            }
+        }
+    }
+}
+
+/// The category at `$idx` and all its descendants, walked in the same query.
+/// Nothing below a deleted category is included, the same in both versions.
+fn category_subtree_sql(idx: usize, category_closure: bool) -> String {
+    if category_closure {
+        return format!(
+            "ARRAY(
+                SELECT cc.descendant_id
+                FROM category_closure cc
+                JOIN category c ON c.doc_id = cc.descendant_id
+                WHERE cc.ancestor_id = ${idx}
+                  AND c.org_id = $1
+                  AND c.is_deleted = false
+                  AND NOT EXISTS (
+                      SELECT 1
+                      FROM category_closure x
+                      JOIN category xa ON xa.doc_id = x.ancestor_id
+                      WHERE x.descendant_id = cc.descendant_id
+                        AND x.depth <= cc.depth
+                        AND xa.is_deleted = true
+                  )
+            )"
+        );
+    }
+
+    // UNION (not UNION ALL) stops the walk if a bad parent_id ever forms a cycle
+    format!(
+        "ARRAY(
+            WITH RECURSIVE category_tree AS (
+                SELECT doc_id
+                FROM category
+                WHERE doc_id = ${idx}
+                  AND org_id = $1
+                  AND is_deleted = false
+
+                UNION
+
+                SELECT c.doc_id
+                FROM category c
+                JOIN category_tree ct ON c.parent_id = ct.doc_id
+                WHERE c.org_id = $1
+                  AND c.is_deleted = false
+            )
+            SELECT doc_id FROM category_tree
+        )"
+    )
+}
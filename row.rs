@@ -1,5 +1,60 @@
+#[cfg(not(test))]
+const JSON_VALIDATE_BATCH_SIZE: i64 = 1000;
+// A few rows span several batches in the tests
+#[cfg(test)]
+const JSON_VALIDATE_BATCH_SIZE: i64 = 3;
+
+/// Why a JSON/JSONB column could not be read into the target type
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonColumnError {
+    MissingColumn {
+        column: String,
+    },
+    // Column exists but is not json/jsonb
+    NotJson {
+        column: String,
+        message: String,
+    },
+    // Doc does not fit the struct, path is where it failed, e.g. "items[2].price"
+    Invalid {
+        column: String,
+        path: String,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for JsonColumnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonColumnError::MissingColumn { column } => write!(f, "Column {} not found", column),
+            JsonColumnError::NotJson { column, message } => {
+                write!(f, "Column {} is not JSON: {}", column, message)
+            }
+            JsonColumnError::Invalid {
+                column,
+                path,
+                message,
+            } => write!(f, "Invalid {} at {}: {}", column, path, message),
+        }
+    }
+}
+
+impl std::error::Error for JsonColumnError {}
+
 pub trait RowJsonExtSafe {
     fn get_jsonb_or_default_safe<T: DeserializeOwned + Default>(&self, column: &str) -> T;
+
+    /// Like get_jsonb_or_default_safe but nothing is swallowed. NULL is read as
+    /// JSON null, so ask for Option<T> when the column is nullable.
+    fn get_jsonb_strict<T: DeserializeOwned>(&self, column: &str) -> Result<T, JsonColumnError>;
+
+    /// Falls back to T::default() like get_jsonb_or_default_safe, but logs the
+    /// failure and pushes it to `errors`
+    fn get_jsonb_or_default_reported<T: DeserializeOwned + Default>(
+        &self,
+        column: &str,
+        errors: &mut Vec<JsonColumnError>,
+    ) -> T;
 }
 
 impl RowJsonExtSafe for Row {
@@ -11,4 +66,256 @@ impl RowJsonExtSafe for Row {
             T::default()
         }
     }
-}
\ No newline at end of file
+
+    fn get_jsonb_strict<T: DeserializeOwned>(&self, column: &str) -> Result<T, JsonColumnError> {
+        if !self.columns().iter().any(|c| c.name() == column) {
+            return Err(JsonColumnError::MissingColumn {
+                column: column.to_string(),
+            });
+        }
+        let value: Option<Value> = self.try_get(column).map_err(|e| JsonColumnError::NotJson {
+            column: column.to_string(),
+            message: e.to_string(),
+        })?;
+        deserialize_json_column(column, value.unwrap_or(Value::Null))
+    }
+
+    fn get_jsonb_or_default_reported<T: DeserializeOwned + Default>(
+        &self,
+        column: &str,
+        errors: &mut Vec<JsonColumnError>,
+    ) -> T {
+        // NULL stays the default here, same as get_jsonb_or_default_safe
+        match self.try_get::<_, Option<Value>>(column) {
+            Ok(None) => T::default(),
+            _ => match self.get_jsonb_strict::<T>(column) {
+                Ok(value) => value,
+                Err(e) => {
+                    tracing::warn!("Using default for {}", e);
+                    errors.push(e);
+                    T::default()
+                }
+            },
+        }
+    }
+}
+
+fn deserialize_json_column<T: DeserializeOwned>(
+    column: &str,
+    value: Value,
+) -> Result<T, JsonColumnError> {
+    serde_path_to_error::deserialize(value).map_err(|e| JsonColumnError::Invalid {
+        column: column.to_string(),
+        path: e.path().to_string(),
+        message: e.inner().to_string(),
+    })
+}
+
+/// A row whose doc does not deserialise into the target struct
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InvalidJsonRow {
+    pub id: String,
+    pub path: String,
+    pub message: String,
+}
+
+/// Scan `column` of every row in `table` and list the rows that do not
+/// deserialise into T. NULL docs are checked as JSON null, use Option<T>
+/// to allow them.
+pub async fn validate_jsonb_column<T: DeserializeOwned>(
+    db: &DBConnection<'_>,
+    table: &'static str,
+    id_column: &'static str,
+    column: &'static str,
+) -> ApiResult<Vec<InvalidJsonRow>> {
+    // Keyset on the id column, OFFSET would rescan every skipped row. The last
+    // id is bound as text and cast back to the type of the column.
+    let id_type: String = db
+        .query_one(
+            "SELECT format_type(atttypid, atttypmod) AS id_type FROM pg_attribute
+            WHERE attrelid = $1::TEXT::REGCLASS AND attname = $2 AND NOT attisdropped",
+            &[&table, &id_column],
+        )
+        .await?
+        .get("id_type");
+    // Not aliased as the id column, ORDER BY would sort by the text then
+    let select = format!(
+        "SELECT {id}::TEXT AS row_id, {column}::JSONB AS doc FROM {table}",
+        id = id_column,
+        column = column,
+        table = table
+    );
+    let first_query = format!("{} ORDER BY {} LIMIT $1", select, id_column);
+    let next_query = format!(
+        "{select} WHERE {id} > $2::TEXT::{id_type} ORDER BY {id} LIMIT $1",
+        select = select,
+        id = id_column,
+        id_type = id_type
+    );
+
+    let mut invalid = Vec::new();
+    let mut last_id: Option<String> = None;
+    loop {
+        let rows = match &last_id {
+            None => db.query(&first_query, &[&JSON_VALIDATE_BATCH_SIZE]).await?,
+            Some(last_id) => {
+                db.query(&next_query, &[&JSON_VALIDATE_BATCH_SIZE, last_id])
+                    .await?
+            }
+        };
+
+        for row in rows.iter() {
+            let doc: Option<Value> = row.get("doc");
+            if let Err(JsonColumnError::Invalid { path, message, .. }) =
+                deserialize_json_column::<T>(column, doc.unwrap_or(Value::Null))
+            {
+                invalid.push(InvalidJsonRow {
+                    id: row.get_as_string("row_id"),
+                    path,
+                    message,
+                });
+            }
+        }
+
+        if (rows.len() as i64) < JSON_VALIDATE_BATCH_SIZE {
+            break;
+        }
+        last_id = rows.last().map(|row| row.get("row_id"));
+    }
+    Ok(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::TestDb;
+
+    #[derive(Debug, Default, PartialEq, Deserialize)]
+    struct Item {
+        name: String,
+        price: f64,
+    }
+
+    async fn json_row(db: &TestDb) -> Result<Row, Box<dyn std::error::Error>> {
+        Ok(db
+            .client()
+            .query_one(
+                "SELECT '{\"name\": \"Tea\", \"price\": 2.5}'::JSONB AS item,
+                    '{\"name\": \"Tea\", \"price\": \"free\"}'::JSONB AS bad_item,
+                    NULL::JSONB AS no_item,
+                    1 AS num",
+                &[],
+            )
+            .await?)
+    }
+
+    #[tokio::test]
+    async fn strict_reports_why_a_column_cannot_be_read() -> Result<(), Box<dyn std::error::Error>> {
+        let db = TestDb::builder().create().await?;
+        let row = json_row(&db).await?;
+
+        assert_eq!(
+            row.get_jsonb_strict::<Item>("item"),
+            Ok(Item {
+                name: "Tea".to_string(),
+                price: 2.5
+            })
+        );
+        assert!(matches!(
+            row.get_jsonb_strict::<Item>("bad_item"),
+            Err(JsonColumnError::Invalid { path, .. }) if path == "price"
+        ));
+        assert!(matches!(
+            row.get_jsonb_strict::<Item>("missing"),
+            Err(JsonColumnError::MissingColumn { .. })
+        ));
+        assert!(matches!(
+            row.get_jsonb_strict::<Item>("num"),
+            Err(JsonColumnError::NotJson { .. })
+        ));
+
+        // NULL is JSON null, only Option<T> accepts it
+        assert_eq!(row.get_jsonb_strict::<Option<Item>>("no_item"), Ok(None));
+        assert!(row.get_jsonb_strict::<Item>("no_item").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reported_falls_back_to_default_and_collects_errors(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let db = TestDb::builder().create().await?;
+        let row = json_row(&db).await?;
+        let mut errors = Vec::new();
+
+        let item: Item = row.get_jsonb_or_default_reported("item", &mut errors);
+        assert_eq!(item.name, "Tea");
+        // NULL is the default without an error
+        let item: Item = row.get_jsonb_or_default_reported("no_item", &mut errors);
+        assert_eq!(item, Item::default());
+        assert!(errors.is_empty());
+
+        let item: Item = row.get_jsonb_or_default_reported("bad_item", &mut errors);
+        assert_eq!(item, Item::default());
+        let item: Item = row.get_jsonb_or_default_reported("missing", &mut errors);
+        assert_eq!(item, Item::default());
+        assert!(matches!(
+            errors.as_slice(),
+            [JsonColumnError::Invalid { .. }, JsonColumnError::MissingColumn { .. }]
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn validate_jsonb_column_reports_bad_rows_across_batches(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // item_doc: bad docs at the end of a batch (i03), right after one (i04),
+        // a NULL doc and the last row. item_seq: the last batch is full, and
+        // 10 sorts before 2 if the keyset compared the ids as text.
+        let db = TestDb::builder()
+            .sql(
+                "CREATE TABLE item_doc (doc_id TEXT PRIMARY KEY, doc JSONB);
+                INSERT INTO item_doc
+                SELECT 'i' || lpad(g::TEXT, 2, '0'), CASE g
+                    WHEN 3 THEN '{\"name\": \"Tea\", \"price\": \"free\"}'::JSONB
+                    WHEN 4 THEN '{\"price\": 1}'::JSONB
+                    WHEN 7 THEN NULL
+                    WHEN 10 THEN '{\"name\": 5, \"price\": 1}'::JSONB
+                    ELSE jsonb_build_object('name', 'Item ' || g, 'price', g)
+                END
+                FROM generate_series(1, 10) g;
+
+                CREATE TABLE item_seq (id BIGINT PRIMARY KEY, doc JSONB);
+                INSERT INTO item_seq
+                SELECT g, CASE WHEN g IN (2, 10, 12)
+                    THEN '{\"name\": \"Tea\"}'::JSONB
+                    ELSE jsonb_build_object('name', 'Item ' || g, 'price', g)
+                END
+                FROM generate_series(1, 12) g;",
+            )
+            .create()
+            .await?;
+        // validate_jsonb_column takes a transaction, the TestDb client is shared
+        let (mut client, connection) = db.config().connect(tokio_postgres::NoTls).await?;
+        tokio::spawn(connection);
+        let tx = client.transaction().await?;
+
+        let invalid = validate_jsonb_column::<Item>(&tx, "item_doc", "doc_id", "doc")
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        let found: Vec<(&str, &str)> = invalid
+            .iter()
+            .map(|row| (row.id.as_str(), row.path.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("i03", "price"), ("i04", "."), ("i07", "."), ("i10", "name")]
+        );
+
+        let invalid = validate_jsonb_column::<Item>(&tx, "item_seq", "id", "doc")
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        let ids: Vec<&str> = invalid.iter().map(|row| row.id.as_str()).collect();
+        assert_eq!(ids, vec!["2", "10", "12"]);
+        Ok(())
+    }
+}
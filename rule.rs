@@ -1,8 +1,89 @@
+// Nesting allowed below the root group and the total number of leaf rules
+const MAX_RULE_DEPTH: usize = 4;
+const MAX_RULES: usize = 100;
+
+const SMART_RULES_PAGE_SIZE: i64 = 50;
+const SMART_RULES_MAX_PAGE_SIZE: i64 = 500;
+
+/// A node of a smart rule tree, either a nested group or a single rule
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SmartRuleNode {
+    Group(SmartRuleGroup),
+    Rule(AttributeSmartRule),
+}
+
+/// Rules combined with "all", "any" or "none"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmartRuleGroup {
+    pub apply_type: String,
+    pub rules: Vec<SmartRuleNode>,
+}
+
+/// A single rule, e.g. match category "drinks"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AttributeSmartRule {
+    // "category", "location", "tag", "price", "uncategorised", ...
+    pub object: String,
+    // "match" or "ignore"
+    pub condition: String,
+    pub value: String,
+    // Category rules also match products in the child categories
+    #[serde(default)]
+    pub include_descendants: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SmartRulesPageInput {
+    #[serde(default)]
+    pub count_only: bool,
+    // "title", "created_on" (default) or "category"
+    pub sort_by: Option<String>,
+    // "asc" (default) or "desc"
+    pub sort_order: Option<String>,
+    // next_cursor from the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AttributeSmartRulesPage {
+    pub no_matches: i64,
+    pub products: Vec<AttributeSmartRulesProductInfo>,
+    pub next_cursor: Option<String>,
+}
+
+/// WHERE clause compiled from a rule tree along with the params it refers to.
+/// The org id is always $1 so the caller can reuse it in the outer query.
+pub struct CompiledSmartRules {
+    pub clause: String,
+    pub params: Vec<Box<dyn ToSql + Sync + Send>>,
+}
+
 /// To get the product count that match the given rules
 pub async fn get_smart_rules(
     db: &DBConnection<'_>,
     org_id: &str,
     input: &AttributeSmartRulesFilter,
+) -> ApiResult<AttributeSmartRulesMatches> {
+    // A flat filter is a tree with a single group
+    let group = SmartRuleGroup {
+        apply_type: input.apply_type.clone(),
+        rules: input
+            .rules
+            .iter()
+            .map(|rule| SmartRuleNode::Rule(rule.clone()))
+            .collect(),
+    };
+    get_smart_rules_tree(db, org_id, &group).await
+}
+
+/// To get the products that match a nested rule group such as
+/// "(category A or B) and not location X"
+pub async fn get_smart_rules_tree(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    input: &SmartRuleGroup,
 ) -> ApiResult<AttributeSmartRulesMatches> {
     // --- CHANGED: Added "none" to valid types ---
     if !matches!(input.apply_type.as_str(), "all" | "any" | "none") {
@@ -17,110 +98,181 @@ pub async fn get_smart_rules(
         });
     }
 
-    let mut query = "SELECT p.doc_id, p.title, p.categories FROM product p ".to_string();
-    let mut where_clauses: Vec<String> = Vec::new();
-    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    let compiled = compile_smart_rules(input, org_id, has_category_closure(db).await?)?;
 
-    let mut to_match_categories = Vec::new();
-    let mut to_ignore_categories = Vec::new();
+    let params: Vec<&(dyn ToSql + Sync)> = compiled
+        .params
+        .iter()
+        .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+        .collect();
 
-    let mut to_match_location = Vec::new();
-    let mut to_ignore_location = Vec::new();
+    let mut query = format!(
+        "SELECT p.doc_id, p.title, p.categories FROM product p WHERE {}",
+        compiled.clause
+    );
 
-    for rule in input.rules.iter() {
-        if rule.object == "category" {
-            if rule.condition == "match" {
-                to_match_categories.push(rule.value.clone());
-            } else {
-                to_ignore_categories.push(rule.value.clone());
-            }
-        } else if rule.object == "location" {
-            if rule.condition == "match" {
-                to_match_location.push(rule.value.clone());
-            } else {
-                to_ignore_location.push(rule.value.clone());
-            }
-        }
-    }
+    // Default org id and active products where query
+    query.push_str(" AND p.org_id = $1 AND p.is_deleted = 'f' AND p.is_archive = 'f'");
 
-    if !to_ignore_location.is_empty() || !to_match_location.is_empty() {
-        query.push_str(" JOIN venue_product vp ON vp.product_id = p.doc_id ");
+    // Adding order by
+    query.push_str(" ORDER BY p.created_on");
 
-        if !to_match_location.is_empty() {
-            where_clauses.push(format!("vp.venue_id = ANY(${})", params.len() + 1));
-            params.push(&to_match_location);
-        }
+    let rows = db.query(&query, &params).await?;
+    let products = map_rule_products(db, org_id, rows).await?;
 
-        if !to_ignore_location.is_empty() {
-            where_clauses.push(format!(
-                "vp.venue_id NOT IN (SELECT UNNEST(${}::text[]))",
-                params.len() + 1
-            ));
-            params.push(&to_ignore_location);
-        }
-    }
+    Ok(AttributeSmartRulesMatches {
+        no_matches: products.len(),
+        products,
+    })
+}
 
-    if !to_match_categories.is_empty() {
-        // --- CHANGED: "all" is the special case, "any" and "none" use '&&' ---
-        if input.apply_type == "all" {
-            // "all": Product must contain ALL specified categories. Use '@>' operator.
-            where_clauses.push(format!("p.categories @> ${}", params.len() + 1));
-        } else {
-            // "any" or "none": Product must contain ANY of the specified categories. Use '&&' operator.
-            // For "none", we will negate the whole block of clauses later.
-            where_clauses.push(format!("p.categories && ${}", params.len() + 1));
-        }
-        params.push(&to_match_categories);
+/// Number of products matching the rules, without loading them
+pub async fn count_smart_rules(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    input: &SmartRuleGroup,
+) -> ApiResult<i64> {
+    if input.rules.is_empty() {
+        return Ok(0);
     }
 
-    if !to_ignore_categories.is_empty() {
-        // This logic is correct: NOT (overlaps) means it must not contain ANY of the ignored categories.
-        where_clauses.push(format!("NOT (p.categories && ${})", params.len() + 1));
-        params.push(&to_ignore_categories);
-    }
+    let compiled = compile_smart_rules(input, org_id, has_category_closure(db).await?)?;
+    count_compiled_rules(db, &compiled).await
+}
 
-    if where_clauses.is_empty() {
-        return Ok(AttributeSmartRulesMatches {
+async fn count_compiled_rules(db: &DBConnection<'_>, compiled: &CompiledSmartRules) -> ApiResult<i64> {
+    let params: Vec<&(dyn ToSql + Sync)> = compiled
+        .params
+        .iter()
+        .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+        .collect();
+
+    let query = format!(
+        "SELECT COUNT(DISTINCT p.doc_id) AS no_matches FROM product p
+        WHERE {} AND p.org_id = $1 AND p.is_deleted = 'f' AND p.is_archive = 'f'",
+        compiled.clause
+    );
+    let row = db.query_one(&query, &params).await?;
+    Ok(row.get("no_matches"))
+}
+
+/// One page of matching products sorted by title, created_on or first category.
+/// With `count_only` only `no_matches` is filled.
+pub async fn get_smart_rules_page(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    input: &SmartRuleGroup,
+    page: &SmartRulesPageInput,
+) -> ApiResult<AttributeSmartRulesPage> {
+    // Sort expression and the type its cursor value is cast back to. NULLs get
+    // a sentinel, the row comparison of the cursor is never true for them.
+    let (sort_column, sort_type) = match page.sort_by.as_deref().unwrap_or("created_on") {
+        "title" => ("COALESCE(p.title, '')", "TEXT"),
+        "created_on" => ("COALESCE(p.created_on, '-infinity')", "TIMESTAMP"),
+        // Same key as sort_by_first_category in catsort
+        "category" => ("COALESCE(p.categories[1], '')", "TEXT"),
+        _ => return Err(ApiError::Error("Invalid sort key".to_string())),
+    };
+    let (order, compare) = match page.sort_order.as_deref().unwrap_or("asc") {
+        "asc" => ("ASC", ">"),
+        "desc" => ("DESC", "<"),
+        _ => return Err(ApiError::Error("Invalid sort order".to_string())),
+    };
+    let limit = page.limit.unwrap_or(SMART_RULES_PAGE_SIZE).clamp(1, SMART_RULES_MAX_PAGE_SIZE);
+
+    if input.rules.is_empty() {
+        return Ok(AttributeSmartRulesPage {
             no_matches: 0,
             products: vec![],
+            next_cursor: None,
         });
     }
 
-    query.push_str(" WHERE ");
-
-    // --- CHANGED: Added block for "none" ---
-    if input.apply_type == "none" {
-        // "none": Negate the "any" logic (NOT (rule1 OR rule2 OR ...))
-        query.push_str(&format!("NOT ({})", &where_clauses.join(" OR ")));
-    } else if input.apply_type == "any" {
-        // "any": (rule1 OR rule2 OR ...)
-        query.push_str(&format!("({})", &where_clauses.join(" OR ")));
-    } else if input.apply_type == "all" {
-        // "all": (rule1 AND rule2 AND ...)
-        query.push_str(&format!("({})", &where_clauses.join(" AND ")));
+    // Compiled once, the count and the page use the same clause and params
+    let compiled = compile_smart_rules(input, org_id, has_category_closure(db).await?)?;
+    let no_matches = count_compiled_rules(db, &compiled).await?;
+    if page.count_only || no_matches == 0 {
+        return Ok(AttributeSmartRulesPage {
+            no_matches,
+            products: vec![],
+            next_cursor: None,
+        });
     }
-    // --- END CHANGE ---
 
+    let mut params: Vec<&(dyn ToSql + Sync)> = compiled
+        .params
+        .iter()
+        .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+        .collect();
 
-    // Default org id and active products where query
+    let mut query = format!(
+        "SELECT p.doc_id, p.title, p.categories, ({})::TEXT AS sort_value FROM product p
+        WHERE {} AND p.org_id = $1 AND p.is_deleted = 'f' AND p.is_archive = 'f'",
+        sort_column, compiled.clause
+    );
+
+    // Keyset pagination on (sort value, doc_id), doc_id breaks ties
+    let cursor: Option<(String, String)> = match &page.cursor {
+        Some(cursor) => Some(
+            serde_json::from_str(cursor)
+                .map_err(|_| ApiError::Error("Invalid cursor".to_string()))?,
+        ),
+        None => None,
+    };
+    if let Some((sort_value, doc_id)) = &cursor {
+        query.push_str(&format!(
+            " AND ({}, p.doc_id) {} (${}::TEXT::{}, ${})",
+            sort_column,
+            compare,
+            params.len() + 1,
+            sort_type,
+            params.len() + 2
+        ));
+        params.push(sort_value);
+        params.push(doc_id);
+    }
+
+    // One extra row tells whether there is a next page
+    let fetch = limit + 1;
     query.push_str(&format!(
-        " AND p.org_id = ${} AND p.is_deleted = 'f' AND p.is_archive = 'f'",
+        " ORDER BY {} {}, p.doc_id {} LIMIT ${}",
+        sort_column,
+        order,
+        order,
         params.len() + 1
     ));
-    params.push(&org_id);
+    params.push(&fetch);
 
-    // Adding group by to handle multiple venue rows for a single product
-    query.push_str(" GROUP BY p.doc_id, p.title, p.categories");
-    
-    // Adding order by
-    query.push_str(" ORDER BY p.created_on");
+    let mut rows = db.query(&query, &params).await?;
+    let next_cursor = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| {
+            let sort_value: String = row.get("sort_value");
+            let doc_id: String = row.get("doc_id");
+            serde_json::to_string(&(sort_value, doc_id)).unwrap_or_default()
+        })
+    } else {
+        None
+    };
 
+    let products = map_rule_products(db, org_id, rows).await?;
+    Ok(AttributeSmartRulesPage {
+        no_matches,
+        products,
+        next_cursor,
+    })
+}
 
+/// Product rows to product info with category titles filled in
+async fn map_rule_products(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    rows: Vec<Row>,
+) -> ApiResult<Vec<AttributeSmartRulesProductInfo>> {
     let mut category_ids: HashSet<String> = HashSet::new();
     let mut categories: HashMap<String, String> = HashMap::new();
     let mut products: Vec<AttributeSmartRulesProductInfo> = Vec::new();
 
-    let rows = db.query(&query, &params).await?;
     for row in rows {
         let categories: Vec<OptionItemString> = row
             .get_as_vec_string("categories")
@@ -164,8 +316,198 @@ pub async fn get_smart_rules(
         }
     }
 
-    Ok(AttributeSmartRulesMatches {
-        no_matches: products.len(),
-        products,
+    Ok(products)
+}
+
+
+
+/// Validate a rule tree and compile it into a single parameterised WHERE clause.
+/// `category_closure` comes from has_category_closure.
+pub fn compile_smart_rules(
+    root: &SmartRuleGroup,
+    org_id: &str,
+    category_closure: bool,
+) -> ApiResult<CompiledSmartRules> {
+    let mut rule_count = 0;
+    let filter = compile_rule_group(root, 0, &mut rule_count)?;
+
+    let mut builder = ProductFilterBuilder::new(org_id);
+    builder.category_closure(category_closure).filter(&filter);
+    let (clause, params) = builder.into_parts();
+    Ok(CompiledSmartRules { clause, params })
+}
+
+fn compile_rule_group(
+    group: &SmartRuleGroup,
+    depth: usize,
+    rule_count: &mut usize,
+) -> ApiResult<ProductFilter> {
+    if depth > MAX_RULE_DEPTH {
+        return Err(ApiError::Error(format!(
+            "Rule groups can be nested at most {} levels",
+            MAX_RULE_DEPTH
+        )));
+    }
+    if !matches!(group.apply_type.as_str(), "all" | "any" | "none") {
+        return Err(ApiError::Error("Invalid apply type".to_string()));
+    }
+    if group.rules.is_empty() {
+        return Err(ApiError::Error("Rule group is empty".to_string()));
+    }
+
+    let mut filters: Vec<ProductFilter> = Vec::new();
+
+    // Sibling rules on the same list column are merged so that "all" can use '@>'
+    // and ignored values become a single NOT (overlaps) clause
+    let mut merged: Vec<(&str, bool, Vec<String>)> = Vec::new();
+
+    for node in group.rules.iter() {
+        let rule = match node {
+            SmartRuleNode::Group(child) => {
+                filters.push(compile_rule_group(child, depth + 1, rule_count)?);
+                continue;
+            }
+            SmartRuleNode::Rule(rule) => rule,
+        };
+
+        *rule_count += 1;
+        if *rule_count > MAX_RULES {
+            return Err(ApiError::Error(format!(
+                "At most {} rules are allowed",
+                MAX_RULES
+            )));
+        }
+
+        let is_match = match rule.condition.as_str() {
+            "match" => true,
+            "ignore" => false,
+            _ => {
+                return Err(ApiError::Error(format!(
+                    "Invalid rule condition: {}",
+                    rule.condition
+                )))
+            }
+        };
+        let condition = |filter: ProductFilter| {
+            if is_match {
+                filter
+            } else {
+                ProductFilter::Not(Box::new(filter))
+            }
+        };
+
+        if rule.include_descendants && rule.object != "category" {
+            return Err(ApiError::Error(format!(
+                "include_descendants is only supported for category rules, not {}",
+                rule.object
+            )));
+        }
+
+        match rule.object.as_str() {
+            "category" if rule.include_descendants => {
+                if rule.value.is_empty() {
+                    return Err(ApiError::Error("Value is required for category rule".to_string()));
+                }
+                // Each subtree is its own clause, "all" means a hit in every subtree
+                filters.push(condition(ProductFilter::InCategoryTree(rule.value.clone())));
+            }
+            "category" | "location" | "tag" | "dietary_option" | "attribute" => {
+                if rule.value.is_empty() {
+                    return Err(ApiError::Error(format!(
+                        "Value is required for {} rule",
+                        rule.object
+                    )));
+                }
+                match merged
+                    .iter_mut()
+                    .find(|(object, matches, _)| *object == rule.object && *matches == is_match)
+                {
+                    Some((_, _, values)) => values.push(rule.value.clone()),
+                    None => merged.push((rule.object.as_str(), is_match, vec![rule.value.clone()])),
+                }
+            }
+            // Value is not used, the object itself is the condition
+            "uncategorised" => filters.push(condition(ProductFilter::Uncategorised)),
+            "unassigned_venue" => filters.push(condition(ProductFilter::UnassignedVenue)),
+            "no_attributes" => filters.push(condition(ProductFilter::NoAttributes)),
+            "price" | "calorie_count" => {
+                let (min, max) = parse_rule_range(&rule.value)?;
+                let column = if rule.object == "price" {
+                    ProductNumberColumn::Price
+                } else {
+                    ProductNumberColumn::CalorieCount
+                };
+                filters.push(condition(ProductFilter::Range { column, min, max }));
+            }
+            "has_alcohol" => {
+                let value: bool = rule.value.parse().map_err(|_| {
+                    ApiError::Error("has_alcohol value must be true or false".to_string())
+                })?;
+                // Ignoring alcoholic products is the same as matching non-alcoholic ones
+                filters.push(ProductFilter::HasAlcohol(value == is_match));
+            }
+            _ => {
+                return Err(ApiError::Error(format!(
+                    "Invalid rule object: {}",
+                    rule.object
+                )))
+            }
+        }
+    }
+
+    for (object, is_match, values) in merged {
+        let filter = match object {
+            "location" => ProductFilter::InVenues(values),
+            _ => {
+                let column = match object {
+                    "category" => ProductListColumn::Categories,
+                    "tag" => ProductListColumn::Tags,
+                    "dietary_option" => ProductListColumn::DietaryOptions,
+                    _ => ProductListColumn::Attributes,
+                };
+                if is_match && group.apply_type == "all" {
+                    // "all": Product must contain ALL specified values
+                    ProductFilter::HasAll(column, values)
+                } else {
+                    // "any" or "none", or ignored values: ANY of the specified values
+                    ProductFilter::HasAny(column, values)
+                }
+            }
+        };
+        // NOT (overlaps) means it must not contain ANY of the ignored values.
+        filters.push(if is_match {
+            filter
+        } else {
+            ProductFilter::Not(Box::new(filter))
+        });
+    }
+
+    Ok(match group.apply_type.as_str() {
+        // "none": Negate the "any" logic (NOT (rule1 OR rule2 OR ...))
+        "none" => ProductFilter::Not(Box::new(ProductFilter::Any(filters))),
+        "any" => ProductFilter::Any(filters),
+        _ => ProductFilter::All(filters),
     })
 }
+
+/// Parse a range rule value such as "100-500", "100-" or "-500"
+fn parse_rule_range(value: &str) -> ApiResult<(Option<f64>, Option<f64>)> {
+    let invalid = || ApiError::Error(format!("Invalid range: {}", value));
+
+    let (min, max) = value.split_once('-').ok_or_else(invalid)?;
+    let parse = |bound: &str| -> ApiResult<Option<f64>> {
+        let bound = bound.trim();
+        if bound.is_empty() {
+            Ok(None)
+        } else {
+            bound.parse().map(Some).map_err(|_| invalid())
+        }
+    };
+
+    let (min, max) = (parse(min)?, parse(max)?);
+    match (min, max) {
+        (None, None) => Err(invalid()),
+        (Some(min), Some(max)) if min > max => Err(invalid()),
+        _ => Ok((min, max)),
+    }
+}
\ No newline at end of file
@@ -1,11 +1,49 @@
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
+/// What to tolerate when a field does not have the expected JSON type.
+/// Mobile clients send "12" for 12, 12 for "12" and "1" for true.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Coerce {
+    // 12 -> "12", true -> "true"
+    pub number_as_string: bool,
+    // "12" -> 12, " 1.5 " -> 1.5
+    pub string_as_number: bool,
+    // "true" / "1" / 1 -> true, "false" / "0" / 0 -> false
+    pub loose_bool: bool,
+}
+
+impl Coerce {
+    pub const STRICT: Coerce = Coerce {
+        number_as_string: false,
+        string_as_number: false,
+        loose_bool: false,
+    };
+
+    pub const LENIENT: Coerce = Coerce {
+        number_as_string: true,
+        string_as_number: true,
+        loose_bool: true,
+    };
+}
+
 pub trait GetValue {
     fn get_as_string(&self, key: &str) -> Option<String>;
     fn get_as_bool(&self, key: &str) -> Option<bool>;
     fn get_as_double(&self, key: &str) -> Option<f64>;
     fn get_as_i64(&self, key: &str) -> Option<i64>;
     fn get_as_vec(&self, key: &str) -> Option<Vec<Value>>;
+
+    /// Nested lookup, either a JSON Pointer ("/doc/sort_info/0/id") or a
+    /// dotted path ("doc.sort_info[0].id"). Keys holding '.' or '[' need the
+    /// pointer form.
+    fn get_path(&self, path: &str) -> Option<&Value>;
+    /// Value at `path` read into any type, None when missing or not matching
+    fn get_path_as<T: DeserializeOwned>(&self, path: &str) -> Option<T>;
+    fn get_path_as_string(&self, path: &str, coerce: Coerce) -> Option<String>;
+    fn get_path_as_bool(&self, path: &str, coerce: Coerce) -> Option<bool>;
+    fn get_path_as_double(&self, path: &str, coerce: Coerce) -> Option<f64>;
+    fn get_path_as_i64(&self, path: &str, coerce: Coerce) -> Option<i64>;
 }
 
 impl GetValue for Value {
@@ -30,4 +68,197 @@ impl GetValue for Value {
     fn get_as_vec(&self, key: &str) -> Option<Vec<Value>> {
         self.get(key).and_then(|v| v.as_array()).cloned()
     }
+
+    fn get_path(&self, path: &str) -> Option<&Value> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        if path.starts_with('/') {
+            return self.pointer(path);
+        }
+
+        let mut current = self;
+        for segment in path.split('.') {
+            // "a..b", ".a" and "a." are typos, not a lookup of "a.b" or "a"
+            if segment.is_empty() {
+                return None;
+            }
+            // "sort_info[0][1]" -> key "sort_info", then indexes 0 and 1
+            let (key, mut rest) = match segment.find('[') {
+                Some(i) => (&segment[..i], &segment[i..]),
+                None => (segment, ""),
+            };
+            if !key.is_empty() {
+                current = current.get(key)?;
+            }
+            while !rest.is_empty() {
+                let end = rest.find(']')?;
+                let index: usize = rest.get(1..end)?.trim().parse().ok()?;
+                current = current.get(index)?;
+                rest = &rest[end + 1..];
+                if !rest.is_empty() && !rest.starts_with('[') {
+                    return None;
+                }
+            }
+        }
+        Some(current)
+    }
+
+    fn get_path_as<T: DeserializeOwned>(&self, path: &str) -> Option<T> {
+        self.get_path(path)
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+    }
+
+    fn get_path_as_string(&self, path: &str, coerce: Coerce) -> Option<String> {
+        match self.get_path(path)? {
+            Value::String(s) => Some(s.to_string()),
+            Value::Number(n) if coerce.number_as_string => Some(n.to_string()),
+            Value::Bool(b) if coerce.number_as_string => Some(b.to_string()),
+            _ => None,
+        }
+    }
+
+    fn get_path_as_bool(&self, path: &str, coerce: Coerce) -> Option<bool> {
+        match self.get_path(path)? {
+            Value::Bool(b) => Some(*b),
+            Value::String(s) if coerce.loose_bool => match s.trim().to_lowercase().as_str() {
+                "true" | "1" => Some(true),
+                "false" | "0" => Some(false),
+                _ => None,
+            },
+            Value::Number(n) if coerce.loose_bool => match n.as_i64() {
+                Some(1) => Some(true),
+                Some(0) => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn get_path_as_double(&self, path: &str, coerce: Coerce) -> Option<f64> {
+        match self.get_path(path)? {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) if coerce.string_as_number => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn get_path_as_i64(&self, path: &str, coerce: Coerce) -> Option<i64> {
+        match self.get_path(path)? {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) if coerce.string_as_number => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn doc() -> Value {
+        json!({
+            "doc": { "sort_info": [{ "id": "a1" }, { "id": "b2" }] },
+            "grid": [[1, 2], [3, 4]],
+            "dotted.key": "x",
+            "price": "12.5",
+            "qty": 3,
+            "flags": { "on": "1", "off": 0, "word": "TRUE", "maybe": 2 }
+        })
+    }
+
+    #[test]
+    fn pointer_and_dotted_paths_find_the_same_value() {
+        let doc = doc();
+        assert_eq!(doc.get_path("/doc/sort_info/1/id"), Some(&json!("b2")));
+        assert_eq!(doc.get_path("doc.sort_info[1].id"), Some(&json!("b2")));
+        assert_eq!(doc.get_path(""), Some(&doc));
+
+        // A key holding '.' only works as a pointer
+        assert_eq!(doc.get_path("/dotted.key"), Some(&json!("x")));
+        assert_eq!(doc.get_path("dotted.key"), None);
+
+        assert_eq!(doc.get_path("doc.missing"), None);
+        assert_eq!(doc.get_path("doc.sort_info[2]"), None);
+    }
+
+    #[test]
+    fn chained_indexes() {
+        let doc = doc();
+        assert_eq!(doc.get_path("grid[1][0]"), Some(&json!(3)));
+        assert_eq!(doc.get_path("grid[ 0 ][1]"), Some(&json!(2)));
+        assert_eq!(json!([{ "id": 7 }]).get_path("[0].id"), Some(&json!(7)));
+        assert_eq!(doc.get_path_as::<Vec<i64>>("grid[1]"), Some(vec![3, 4]));
+    }
+
+    #[test]
+    fn bad_brackets_and_empty_segments_are_none() {
+        let doc = doc();
+        for path in [
+            "grid[0",
+            "grid[x]",
+            "grid[-1]",
+            "grid[]",
+            "grid[0]x",
+            "grid[0]]",
+            "grid]0[",
+            "doc..sort_info",
+            ".doc",
+            "doc.",
+        ] {
+            assert_eq!(doc.get_path(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn strict_reads_only_matching_types() {
+        let doc = doc();
+        assert_eq!(doc.get_path_as_string("doc.sort_info[0].id", Coerce::STRICT), Some("a1".to_string()));
+        assert_eq!(doc.get_path_as_string("qty", Coerce::STRICT), None);
+        assert_eq!(doc.get_path_as_double("price", Coerce::STRICT), None);
+        assert_eq!(doc.get_path_as_i64("qty", Coerce::STRICT), Some(3));
+        assert_eq!(doc.get_path_as_bool("flags.on", Coerce::STRICT), None);
+    }
+
+    #[test]
+    fn number_as_string() {
+        let doc = doc();
+        let coerce = Coerce {
+            number_as_string: true,
+            ..Coerce::STRICT
+        };
+        assert_eq!(doc.get_path_as_string("qty", coerce), Some("3".to_string()));
+        assert_eq!(json!({ "b": true }).get_path_as_string("b", coerce), Some("true".to_string()));
+        // Only the one direction
+        assert_eq!(doc.get_path_as_double("price", coerce), None);
+    }
+
+    #[test]
+    fn string_as_number() {
+        let doc = doc();
+        let coerce = Coerce {
+            string_as_number: true,
+            ..Coerce::STRICT
+        };
+        assert_eq!(doc.get_path_as_double("price", coerce), Some(12.5));
+        assert_eq!(json!({ "n": " 42 " }).get_path_as_i64("n", coerce), Some(42));
+        assert_eq!(doc.get_path_as_i64("price", coerce), None);
+        assert_eq!(doc.get_path_as_string("qty", coerce), None);
+        assert_eq!(doc.get_path_as_bool("flags.on", coerce), None);
+    }
+
+    #[test]
+    fn loose_bool() {
+        let doc = doc();
+        let coerce = Coerce {
+            loose_bool: true,
+            ..Coerce::STRICT
+        };
+        assert_eq!(doc.get_path_as_bool("flags.on", coerce), Some(true));
+        assert_eq!(doc.get_path_as_bool("flags.off", coerce), Some(false));
+        assert_eq!(doc.get_path_as_bool("flags.word", coerce), Some(true));
+        assert_eq!(doc.get_path_as_bool("flags.maybe", coerce), None);
+        assert_eq!(doc.get_path_as_double("price", coerce), None);
+    }
 }
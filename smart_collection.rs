@@ -0,0 +1,425 @@
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_postgres::{types::ToSql, AsyncMessage, NoTls};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmartCollectionInput {
+    // Existing collection to update, None to create
+    pub id: Option<String>,
+    pub title: String,
+    pub rules: SmartRuleGroup,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProductChanged {
+    org_id: Option<String>,
+    product_id: String,
+}
+
+/// Save a named rule set for the org and rebuild its membership
+pub async fn save_smart_collection(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    input: &SmartCollectionInput,
+) -> ApiResult<String> {
+    if input.title.trim().is_empty() {
+        return Err(ApiError::Error("Title is required".to_string()));
+    }
+    if input.rules.rules.is_empty() {
+        return Err(ApiError::Error("At least one rule is required".to_string()));
+    }
+    // Reject invalid rules before anything is stored, the SQL is not used
+    compile_smart_rules(&input.rules, org_id, false)?;
+
+    let rules = serde_json::to_value(&input.rules)?;
+    let collection_id: String = match &input.id {
+        Some(id) => db
+            .query_opt(
+                "UPDATE smart_collection SET title = $3, rules = $4, modified_on = current_timestamp
+                WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false
+                RETURNING doc_id",
+                &[id, &org_id, &input.title, &rules],
+            )
+            .await?
+            .ok_or_else(|| ApiError::Error("Invalid collection id".to_string()))?
+            .get("doc_id"),
+        None => db
+            .query_one(
+                "INSERT INTO smart_collection (org_id, title, rules) VALUES ($1, $2, $3)
+                RETURNING doc_id",
+                &[&org_id, &input.title, &rules],
+            )
+            .await?
+            .get("doc_id"),
+    };
+
+    refresh_smart_collection(db, org_id, &collection_id).await?;
+    Ok(collection_id)
+}
+
+pub async fn delete_smart_collection(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    collection_id: &str,
+) -> ApiResult<bool> {
+    db.execute(
+        "DELETE FROM smart_collection_product WHERE collection_id = $1 AND org_id = $2",
+        &[&collection_id, &org_id],
+    )
+    .await?;
+    let affected = db
+        .execute(
+            "UPDATE smart_collection SET is_deleted = true, modified_on = current_timestamp
+            WHERE doc_id = $1 AND org_id = $2",
+            &[&collection_id, &org_id],
+        )
+        .await?;
+    Ok(affected != 0)
+}
+
+/// Recompute every member of a collection
+pub async fn refresh_smart_collection(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    collection_id: &str,
+) -> ApiResult<()> {
+    let row = db
+        .query_opt(
+            "SELECT rules FROM smart_collection WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+            &[&collection_id, &org_id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::Error("Invalid collection id".to_string()))?;
+    let rules: SmartRuleGroup = row.get_as_struct("rules")?;
+
+    db.execute(
+        "DELETE FROM smart_collection_product WHERE collection_id = $1 AND org_id = $2",
+        &[&collection_id, &org_id],
+    )
+    .await?;
+
+    let compiled = compile_smart_rules(&rules, org_id, has_category_closure(db).await?)?;
+    let mut params: Vec<&(dyn ToSql + Sync)> = compiled
+        .params
+        .iter()
+        .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+        .collect();
+
+    let query = format!(
+        "INSERT INTO smart_collection_product (collection_id, product_id, org_id)
+        SELECT ${}, p.doc_id, p.org_id FROM product p
+        WHERE {} AND p.org_id = $1 AND p.is_deleted = 'f' AND p.is_archive = 'f'
+        ON CONFLICT DO NOTHING",
+        params.len() + 1,
+        compiled.clause
+    );
+    params.push(&collection_id);
+
+    db.execute(&query, &params).await?;
+    Ok(())
+}
+
+pub async fn refresh_org_smart_collections(db: &DBConnection<'_>, org_id: &str) -> ApiResult<()> {
+    let rows = db
+        .query(
+            "SELECT doc_id FROM smart_collection WHERE org_id = $1 AND is_deleted = false",
+            &[&org_id],
+        )
+        .await?;
+    for row in rows {
+        refresh_smart_collection(db, org_id, &row.get_as_string("doc_id")).await?;
+    }
+    Ok(())
+}
+
+/// Re-evaluate one product against every collection of its org
+pub async fn sync_product_collections(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    product_id: &str,
+) -> ApiResult<()> {
+    let rows = db
+        .query(
+            "SELECT doc_id, rules FROM smart_collection WHERE org_id = $1 AND is_deleted = false",
+            &[&org_id],
+        )
+        .await?;
+
+    let category_closure = has_category_closure(db).await?;
+    for row in rows {
+        let collection_id = row.get_as_string("doc_id");
+        let rules: SmartRuleGroup = row.get_as_struct("rules")?;
+        let compiled = compile_smart_rules(&rules, org_id, category_closure)?;
+        let mut params: Vec<&(dyn ToSql + Sync)> = compiled
+            .params
+            .iter()
+            .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+
+        let query = format!(
+            "SELECT 1 FROM product p
+            WHERE {} AND p.org_id = $1 AND p.is_deleted = 'f' AND p.is_archive = 'f'
+              AND p.doc_id = ${}",
+            compiled.clause,
+            params.len() + 1
+        );
+        params.push(&product_id);
+
+        if db.query_opt(&query, &params).await?.is_some() {
+            db.execute(
+                "INSERT INTO smart_collection_product (collection_id, product_id, org_id)
+                VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                &[&collection_id, &product_id, &org_id],
+            )
+            .await?;
+        } else {
+            db.execute(
+                "DELETE FROM smart_collection_product WHERE collection_id = $1 AND product_id = $2",
+                &[&collection_id, &product_id],
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Collections the product currently belongs to
+pub async fn get_product_collections(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    product_id: &str,
+) -> ApiResult<Vec<OptionItemString>> {
+    let rows = db
+        .query(
+            "SELECT sc.doc_id, sc.title
+            FROM smart_collection_product scp
+            JOIN smart_collection sc ON sc.doc_id = scp.collection_id
+            WHERE scp.org_id = $1 AND scp.product_id = $2 AND sc.is_deleted = false
+            ORDER BY sc.title",
+            &[&org_id, &product_id],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| OptionItemString {
+            id: row.get_as_string("doc_id"),
+            title: row.get_as_string("title"),
+        })
+        .collect())
+}
+
+const WORKER_RETRY_MIN: Duration = Duration::from_secs(1);
+const WORKER_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// Background worker keeping collection membership current. Runs until the
+/// process ends: a lost connection is reopened with backoff, and every org is
+/// refreshed after (re)connecting to catch changes notified while it was down.
+pub async fn run_smart_collection_worker(url: &str) {
+    let mut delay = WORKER_RETRY_MIN;
+    loop {
+        let mut connected = false;
+        if let Err(e) = run_worker_session(url, &mut connected).await {
+            eprintln!("Smart collection worker stopped: {}", e);
+        }
+        // Back off only while the database stays unreachable
+        if connected {
+            delay = WORKER_RETRY_MIN;
+        }
+        eprintln!("Smart collection worker reconnecting in {:?}", delay);
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(WORKER_RETRY_MAX);
+    }
+}
+
+/// Listens on a dedicated connection and applies changes through a second one.
+/// Returns when either connection fails.
+async fn run_worker_session(
+    url: &str,
+    connected: &mut bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (listener, mut connection) = tokio_postgres::connect(url, NoTls).await?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // Notifications only arrive by polling the connection
+    let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+    tokio::spawn(async move {
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if tx.send(notification).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Listener connection failed: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    listener
+        .batch_execute("LISTEN product_changed; LISTEN smart_collection_refresh;")
+        .await?;
+
+    let (mut client, connection) = tokio_postgres::connect(url, NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+    *connected = true;
+
+    // Listening already, so nothing falls between the catch up and the first batch
+    let rows = client
+        .query(
+            "SELECT DISTINCT org_id FROM smart_collection WHERE is_deleted = false",
+            &[],
+        )
+        .await?;
+    let orgs: HashSet<String> = rows.iter().map(|row| row.get_as_string("org_id")).collect();
+    apply_changes(&mut client, &orgs, &HashSet::new()).await?;
+
+    while let Some(first) = rx.recv().await {
+        // Bulk imports fire one notification per row, handle each product once per batch
+        let mut products: HashSet<(String, String)> = HashSet::new();
+        let mut orgs: HashSet<String> = HashSet::new();
+        let mut batch = vec![first];
+        while let Ok(notification) = rx.try_recv() {
+            batch.push(notification);
+        }
+
+        for notification in batch {
+            if notification.channel() == "smart_collection_refresh" {
+                orgs.insert(notification.payload().to_string());
+                continue;
+            }
+            match serde_json::from_str::<ProductChanged>(notification.payload()) {
+                Ok(ProductChanged {
+                    org_id: Some(org_id),
+                    product_id,
+                }) => {
+                    products.insert((org_id, product_id));
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Invalid product_changed payload: {}", e),
+            }
+        }
+
+        apply_changes(&mut client, &orgs, &products).await?;
+    }
+
+    Err("Listener connection closed".into())
+}
+
+/// One transaction per item, a failed item rolls back alone instead of
+/// aborting the transaction for the rest of the batch. Only connection level
+/// errors are returned.
+async fn apply_changes(
+    client: &mut tokio_postgres::Client,
+    orgs: &HashSet<String>,
+    products: &HashSet<(String, String)>,
+) -> Result<(), tokio_postgres::Error> {
+    for org_id in orgs.iter() {
+        let db = client.transaction().await?;
+        match refresh_org_smart_collections(&db, org_id).await {
+            Ok(()) => db.commit().await?,
+            Err(e) => eprintln!("Smart collection refresh failed for {}: {:?}", org_id, e),
+        }
+    }
+    for (org_id, product_id) in products.iter() {
+        // A full refresh of the org already covered this product
+        if orgs.contains(org_id) {
+            continue;
+        }
+        let db = client.transaction().await?;
+        match sync_product_collections(&db, org_id, product_id).await {
+            Ok(()) => db.commit().await?,
+            Err(e) => eprintln!("Smart collection sync failed for {}: {:?}", product_id, e),
+        }
+    }
+    Ok(())
+}
+
+const PREVIEW_SAMPLE_SIZE: usize = 10;
+const PREVIEW_MAX_SAMPLE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmartRulesPreviewInput {
+    // Saved collection whose rules are compared, None to compare against no rules
+    pub collection_id: Option<String>,
+    pub rules: SmartRuleGroup,
+    pub sample_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmartRulesPreview {
+    pub current_matches: usize,
+    pub proposed_matches: usize,
+    pub no_added: usize,
+    pub no_removed: usize,
+    pub added: Vec<AttributeSmartRulesProductInfo>,
+    pub removed: Vec<AttributeSmartRulesProductInfo>,
+}
+
+/// Products that would join or leave the collection if the proposed rules were saved.
+/// Nothing is written.
+pub async fn preview_smart_rules(
+    db: &DBConnection<'_>,
+    org_id: &str,
+    input: &SmartRulesPreviewInput,
+) -> ApiResult<SmartRulesPreview> {
+    let sample_size = input
+        .sample_size
+        .unwrap_or(PREVIEW_SAMPLE_SIZE)
+        .min(PREVIEW_MAX_SAMPLE_SIZE);
+
+    let current = match &input.collection_id {
+        Some(collection_id) => {
+            let row = db
+                .query_opt(
+                    "SELECT rules FROM smart_collection
+                    WHERE doc_id = $1 AND org_id = $2 AND is_deleted = false",
+                    &[collection_id, &org_id],
+                )
+                .await?
+                .ok_or_else(|| ApiError::Error("Invalid collection id".to_string()))?;
+            let rules: SmartRuleGroup = row.get_as_struct("rules")?;
+            get_smart_rules_tree(db, org_id, &rules).await?
+        }
+        None => AttributeSmartRulesMatches {
+            no_matches: 0,
+            products: vec![],
+        },
+    };
+    let proposed = get_smart_rules_tree(db, org_id, &input.rules).await?;
+
+    let current_ids: HashSet<&str> = current.products.iter().map(|p| p.id.as_str()).collect();
+    let proposed_ids: HashSet<&str> = proposed.products.iter().map(|p| p.id.as_str()).collect();
+
+    // Both lists keep the engine's created_on order
+    let added: Vec<&AttributeSmartRulesProductInfo> = proposed
+        .products
+        .iter()
+        .filter(|p| !current_ids.contains(p.id.as_str()))
+        .collect();
+    let removed: Vec<&AttributeSmartRulesProductInfo> = current
+        .products
+        .iter()
+        .filter(|p| !proposed_ids.contains(p.id.as_str()))
+        .collect();
+
+    Ok(SmartRulesPreview {
+        current_matches: current.no_matches,
+        proposed_matches: proposed.no_matches,
+        no_added: added.len(),
+        no_removed: removed.len(),
+        added: added.into_iter().take(sample_size).cloned().collect(),
+        removed: removed.into_iter().take(sample_size).cloned().collect(),
+    })
+}
@@ -0,0 +1,78 @@
+CREATE TABLE IF NOT EXISTS smart_collection (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    doc_id VARCHAR(24) NOT NULL UNIQUE DEFAULT substr(encode(gen_random_bytes(12), 'hex'), 1, 24),
+    created_on TIMESTAMP DEFAULT now(),
+    modified_on TIMESTAMP DEFAULT now(),
+    org_id VARCHAR(24) NOT NULL,
+    title VARCHAR(128) NOT NULL,
+    -- SmartRuleGroup from rule.rs
+    rules JSONB NOT NULL,
+    is_deleted BOOLEAN NOT NULL DEFAULT false
+);
+
+CREATE INDEX IF NOT EXISTS smart_collection_org ON smart_collection (org_id) WHERE is_deleted = false;
+
+CREATE TABLE IF NOT EXISTS smart_collection_product (
+    collection_id VARCHAR(24) NOT NULL REFERENCES smart_collection(doc_id) ON DELETE CASCADE,
+    product_id VARCHAR(24) NOT NULL,
+    org_id VARCHAR(24) NOT NULL,
+    created_on TIMESTAMP DEFAULT now(),
+    PRIMARY KEY (collection_id, product_id)
+);
+
+-- "Which collections does product X belong to"
+CREATE INDEX IF NOT EXISTS smart_collection_product_product
+    ON smart_collection_product (org_id, product_id);
+
+
+-- Product and venue changes are re-evaluated for that one product
+CREATE OR REPLACE FUNCTION notify_product_changed() RETURNS trigger AS $$
+DECLARE
+    rec RECORD;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        rec := OLD;
+    ELSE
+        rec := NEW;
+    END IF;
+
+    IF TG_TABLE_NAME = 'venue_product' THEN
+        PERFORM pg_notify('product_changed', json_build_object(
+            'org_id', (SELECT org_id FROM product WHERE doc_id = rec.product_id),
+            'product_id', rec.product_id
+        )::text);
+    ELSE
+        PERFORM pg_notify('product_changed', json_build_object(
+            'org_id', rec.org_id,
+            'product_id', rec.doc_id
+        )::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS product_changed ON product;
+CREATE TRIGGER product_changed
+    AFTER INSERT OR UPDATE OR DELETE ON product
+    FOR EACH ROW EXECUTE FUNCTION notify_product_changed();
+
+DROP TRIGGER IF EXISTS venue_product_changed ON venue_product;
+CREATE TRIGGER venue_product_changed
+    AFTER INSERT OR UPDATE OR DELETE ON venue_product
+    FOR EACH ROW EXECUTE FUNCTION notify_product_changed();
+
+
+-- Moving or deleting a category can change descendant rules, refresh the whole org
+CREATE OR REPLACE FUNCTION notify_category_tree_changed() RETURNS trigger AS $$
+BEGIN
+    IF NEW.parent_id IS DISTINCT FROM OLD.parent_id OR NEW.is_deleted IS DISTINCT FROM OLD.is_deleted THEN
+        PERFORM pg_notify('smart_collection_refresh', NEW.org_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS category_tree_changed ON category;
+CREATE TRIGGER category_tree_changed
+    AFTER UPDATE ON category
+    FOR EACH ROW EXECUTE FUNCTION notify_category_tree_changed();
@@ -40,4 +40,27 @@ SELECT
     start_doc_id,
     name
 FROM category_path
-ORDER BY start_doc_id, depth DESC;
\ No newline at end of file
+ORDER BY start_doc_id, depth DESC;
+
+-- Same breadcrumbs from category_closure (category_closure.sql). Like the walk
+-- above it stops at the first deleted or other org ancestor, depth 0 is the
+-- start category itself, so a deleted start category returns nothing.
+SELECT
+    cc.descendant_id AS start_doc_id,
+    c.name
+FROM category_closure cc
+JOIN category c
+    ON c.doc_id = cc.ancestor_id
+WHERE cc.descendant_id = ANY($1)
+  AND c.org_id = $2
+  AND c.is_deleted = false
+  AND NOT EXISTS (
+      SELECT 1
+      FROM category_closure x
+      JOIN category xa
+          ON xa.doc_id = x.ancestor_id
+      WHERE x.descendant_id = cc.descendant_id
+        AND x.depth <= cc.depth
+        AND (xa.is_deleted = true OR xa.org_id <> $2)
+  )
+ORDER BY start_doc_id, cc.depth DESC;
@@ -0,0 +1,432 @@
+use argh::FromArgs;
+use std::fs;
+use std::path::PathBuf;
+
+#[path = "insert_query.rs"]
+mod insert_query;
+
+use insert_query::{
+    create_insert_from_create_table, create_select_from_create_table,
+    create_update_from_create_table, create_upsert_from_create_table, parse_create_tables,
+    TableDef,
+};
+
+/// Generate Rust structs, row mapping and CRUD functions from CREATE TABLE DDL.
+#[derive(FromArgs, Debug)]
+struct Cli {
+    /// sql file with CREATE TABLE statements (data.sql, H.sql, ...)
+    #[argh(positional)]
+    input: PathBuf,
+
+    /// only generate these tables (repeatable)
+    #[argh(option, short = 't')]
+    table: Vec<String>,
+
+    /// write to this file instead of stdout
+    #[argh(option, short = 'o')]
+    out: Option<PathBuf>,
+}
+
+// Strict and reserved keywords up to the 2024 edition, usable as r#keyword
+const RUST_KEYWORDS: [&str; 48] = [
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try", "typeof",
+    "unsized", "virtual", "yield",
+];
+
+// Keywords that cannot be raw identifiers, the field gets a trailing underscore
+const RUST_PATH_KEYWORDS: [&str; 3] = ["self", "super", "crate"];
+
+/// Pull every CREATE TABLE ... ); out of a file that may also hold notes,
+/// INSERTs or broken statements, so one bad block does not stop the rest
+fn extract_create_tables(source: &str) -> Vec<String> {
+    // ASCII only, so byte offsets in `upper` are the same as in `source`
+    let upper = source.to_ascii_uppercase();
+    let mut statements = Vec::new();
+    let mut from = 0;
+
+    while let Some(offset) = upper[from..].find("CREATE TABLE") {
+        let start = from + offset;
+        let mut depth = 0;
+        let mut end = None;
+        let mut in_quote = false;
+
+        for (i, ch) in source[start..].char_indices() {
+            match ch {
+                '\'' => in_quote = !in_quote,
+                '(' if !in_quote => depth += 1,
+                ')' if !in_quote => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(start + i + 1);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match end {
+            Some(end) => {
+                statements.push(format!("{};", &source[start..end]));
+                from = end;
+            }
+            None => break,
+        }
+    }
+    statements
+}
+
+/// Rust type for a column, Option<..> when the column can be NULL
+fn rust_type(data_type: &str, nullable: bool) -> String {
+    let upper = data_type.trim().to_uppercase();
+
+    let (base, is_array) = if let Some(base) = upper.strip_suffix("[]") {
+        (base.trim().to_string(), true)
+    } else if let Some(base) = upper.strip_prefix("ARRAY<").and_then(|t| t.strip_suffix('>')) {
+        (base.trim().to_string(), true)
+    } else {
+        (upper, false)
+    };
+    // VARCHAR(128) -> VARCHAR, NUMERIC(10, 2) -> NUMERIC
+    let base = base.split('(').next().unwrap_or("").trim();
+
+    let ty = match base {
+        "BIGSERIAL" | "SERIAL8" | "BIGINT" | "INT8" => "i64",
+        "SERIAL" | "SERIAL4" | "INT" | "INTEGER" | "INT4" => "i32",
+        "SMALLSERIAL" | "SERIAL2" | "SMALLINT" | "INT2" => "i16",
+        "VARCHAR" | "CHARACTER VARYING" | "CHAR" | "CHARACTER" | "TEXT" | "CITEXT" => "String",
+        "BOOLEAN" | "BOOL" => "bool",
+        "REAL" | "FLOAT4" => "f32",
+        "DOUBLE PRECISION" | "DOUBLE" | "FLOAT8" | "FLOAT" => "f64",
+        // tokio-postgres needs the with-rust_decimal feature for NUMERIC
+        "NUMERIC" | "DECIMAL" => "rust_decimal::Decimal",
+        "TIMESTAMP" | "TIMESTAMP WITHOUT TIME ZONE" => "chrono::NaiveDateTime",
+        "TIMESTAMPTZ" | "TIMESTAMP WITH TIME ZONE" => "chrono::DateTime<chrono::Utc>",
+        "DATE" => "chrono::NaiveDate",
+        "TIME" | "TIME WITHOUT TIME ZONE" => "chrono::NaiveTime",
+        "JSON" | "JSONB" => "serde_json::Value",
+        "UUID" => "uuid::Uuid",
+        "BYTEA" => "Vec<u8>",
+        _ => "String",
+    };
+
+    let ty = if is_array {
+        format!("Vec<{}>", ty)
+    } else {
+        ty.to_string()
+    };
+    if nullable {
+        format!("Option<{}>", ty)
+    } else {
+        ty
+    }
+}
+
+fn unquote(name: &str) -> &str {
+    name.trim_matches('"')
+}
+
+fn snake_case(column: &str) -> String {
+    let mut field = String::new();
+    for (i, ch) in unquote(column).chars().enumerate() {
+        if ch.is_uppercase() {
+            if i > 0 && !field.ends_with('_') {
+                field.push('_');
+            }
+            field.extend(ch.to_lowercase());
+        } else if ch.is_alphanumeric() {
+            field.push(ch);
+        } else {
+            field.push('_');
+        }
+    }
+    field
+}
+
+/// Struct field for a column. The column name itself stays in the serde
+/// rename and the row.try_get of the generated code.
+fn field_name(column: &str) -> String {
+    let field = snake_case(column);
+    if RUST_KEYWORDS.contains(&field.as_str()) {
+        format!("r#{}", field)
+    } else if RUST_PATH_KEYWORDS.contains(&field.as_str()) {
+        format!("{}_", field)
+    } else {
+        field
+    }
+}
+
+fn struct_name(table: &str) -> String {
+    // Drop the schema, public.ai_agent -> AiAgent
+    let table = unquote(table.rsplit('.').next().unwrap_or(table));
+    table
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn without_semicolon(sql: &str) -> &str {
+    sql.trim_end_matches(';')
+}
+
+fn generate(table: &TableDef, ddl: &str) -> String {
+    let name = struct_name(&table.name);
+    // Only ever a suffix, insert_type, so no keyword escaping
+    let fn_name = snake_case(table.name.rsplit('.').next().unwrap_or(&table.name));
+    let mut out = String::new();
+
+    // Struct
+    out.push_str(&format!("#[derive(Debug, Clone, Serialize, Deserialize)]\npub struct {} {{\n", name));
+    for column in table.columns.iter() {
+        let field = field_name(&column.name);
+        if field.trim_start_matches("r#") != unquote(&column.name) {
+            out.push_str(&format!("    #[serde(rename = \"{}\")]\n", unquote(&column.name)));
+        }
+        out.push_str(&format!(
+            "    pub {}: {},\n",
+            field,
+            rust_type(&column.data_type, column.nullable)
+        ));
+    }
+    out.push_str("}\n\n");
+
+    // Row mapping
+    out.push_str(&format!(
+        "impl TryFrom<&Row> for {} {{\n    type Error = tokio_postgres::Error;\n\n    fn try_from(row: &Row) -> Result<Self, Self::Error> {{\n        Ok({} {{\n",
+        name, name
+    ));
+    for column in table.columns.iter() {
+        out.push_str(&format!(
+            "            {}: row.try_get(\"{}\")?,\n",
+            field_name(&column.name),
+            unquote(&column.name)
+        ));
+    }
+    out.push_str("        })\n    }\n}\n\n");
+
+    let writable: Vec<String> = table
+        .columns
+        .iter()
+        .filter(|c| !c.is_generated)
+        .map(|c| format!("&item.{}", field_name(&c.name)))
+        .collect();
+    let non_key_writable: Vec<String> = table
+        .columns
+        .iter()
+        .filter(|c| !c.is_generated && !table.primary_key.contains(&c.name))
+        .map(|c| format!("&item.{}", field_name(&c.name)))
+        .collect();
+    let key_args: Vec<String> = table
+        .primary_key
+        .iter()
+        .filter_map(|k| table.columns.iter().find(|c| &c.name == k))
+        .map(|c| format!("{}: {}", field_name(&c.name), rust_type(&c.data_type, false)))
+        .collect();
+    let key_params: Vec<String> = table
+        .primary_key
+        .iter()
+        .map(|k| format!("&{}", field_name(k)))
+        .collect();
+    let key_item_params: Vec<String> = table
+        .primary_key
+        .iter()
+        .map(|k| format!("&item.{}", field_name(k)))
+        .collect();
+
+    // Insert, returning the stored row so generated columns are filled in
+    if let Some(sql) = create_insert_from_create_table(ddl) {
+        out.push_str(&format!(
+            "pub async fn insert_{fn_name}(db: &Client, item: &{name}) -> Result<{name}, tokio_postgres::Error> {{\n    let row = db\n        .query_one(\n            \"{} RETURNING *\",\n            &[{}],\n        )\n        .await?;\n    {name}::try_from(&row)\n}}\n\n",
+            escape(without_semicolon(&sql)),
+            writable.join(", ")
+        ));
+    }
+
+    if let Some(sql) = create_upsert_from_create_table(ddl) {
+        out.push_str(&format!(
+            "pub async fn upsert_{fn_name}(db: &Client, item: &{name}) -> Result<u64, tokio_postgres::Error> {{\n    db.execute(\n        \"{}\",\n        &[{}],\n    )\n    .await\n}}\n\n",
+            escape(without_semicolon(&sql)),
+            writable.join(", ")
+        ));
+    }
+
+    // The rest need a primary key
+    if !table.primary_key.is_empty() {
+        if let Some(sql) = create_select_from_create_table(ddl) {
+            out.push_str(&format!(
+                "pub async fn get_{fn_name}(db: &Client, {}) -> Result<Option<{name}>, tokio_postgres::Error> {{\n    let row = db\n        .query_opt(\n            \"{}\",\n            &[{}],\n        )\n        .await?;\n    row.as_ref().map({name}::try_from).transpose()\n}}\n\n",
+                key_args.join(", "),
+                escape(without_semicolon(&sql)),
+                key_params.join(", ")
+            ));
+        }
+
+        if let Some(sql) = create_update_from_create_table(ddl) {
+            let mut params = non_key_writable.clone();
+            params.extend(key_item_params.iter().cloned());
+            out.push_str(&format!(
+                "pub async fn update_{fn_name}(db: &Client, item: &{name}) -> Result<u64, tokio_postgres::Error> {{\n    db.execute(\n        \"{}\",\n        &[{}],\n    )\n    .await\n}}\n\n",
+                escape(without_semicolon(&sql)),
+                params.join(", ")
+            ));
+        }
+
+        let keys: Vec<String> = table
+            .primary_key
+            .iter()
+            .enumerate()
+            .map(|(i, k)| format!("{} = ${}", k, i + 1))
+            .collect();
+        out.push_str(&format!(
+            "pub async fn delete_{fn_name}(db: &Client, {}) -> Result<u64, tokio_postgres::Error> {{\n    db.execute(\n        \"{}\",\n        &[{}],\n    )\n    .await\n}}\n\n",
+            key_args.join(", "),
+            escape(&format!("DELETE FROM {} WHERE {}", table.name, keys.join(" AND "))),
+            key_params.join(", ")
+        ));
+    }
+
+    out
+}
+
+/// Quoted identifiers end up inside a Rust string literal
+fn escape(sql: &str) -> String {
+    sql.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn main() {
+    let cli: Cli = argh::from_env();
+
+    let source = match fs::read_to_string(&cli.input) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", cli.input.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut out = String::from(
+        "use serde::{Deserialize, Serialize};\nuse tokio_postgres::{Client, Row};\n\n",
+    );
+    let mut generated = 0;
+
+    for ddl in extract_create_tables(&source) {
+        let table = match parse_create_tables(&ddl) {
+            Ok(tables) => match tables.into_iter().next() {
+                Some(table) => table,
+                None => continue,
+            },
+            Err(e) => {
+                let first_line = ddl.lines().next().unwrap_or("");
+                eprintln!("Skipping `{}`: {}", first_line.trim(), e);
+                continue;
+            }
+        };
+
+        if !cli.table.is_empty() && !cli.table.iter().any(|t| t == unquote(&table.name)) {
+            continue;
+        }
+
+        out.push_str(&generate(&table, &ddl));
+        generated += 1;
+    }
+
+    if generated == 0 {
+        eprintln!("No tables found in {}", cli.input.display());
+        std::process::exit(1);
+    }
+
+    match &cli.out {
+        Some(path) => {
+            if let Err(e) = fs::write(path, out) {
+                eprintln!("Cannot write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            println!("Generated {} tables into {}", generated, path.display());
+        }
+        None => print!("{}", out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rust_type_maps_sizes_arrays_and_nullability() {
+        assert_eq!(rust_type("BIGSERIAL", false), "i64");
+        assert_eq!(rust_type("varchar(128)", false), "String");
+        assert_eq!(rust_type("NUMERIC(10, 2)", true), "Option<rust_decimal::Decimal>");
+        assert_eq!(rust_type("TEXT[]", false), "Vec<String>");
+        assert_eq!(rust_type("ARRAY<INT>", true), "Option<Vec<i32>>");
+        assert_eq!(rust_type("TIMESTAMP WITH TIME ZONE", false), "chrono::DateTime<chrono::Utc>");
+        assert_eq!(rust_type("ltree", false), "String");
+    }
+
+    #[test]
+    fn field_name_is_snake_case_and_escapes_keywords() {
+        assert_eq!(field_name("\"SortNo\""), "sort_no");
+        assert_eq!(field_name("created_on"), "created_on");
+        assert_eq!(field_name("unit-price"), "unit_price");
+        assert_eq!(field_name("type"), "r#type");
+        assert_eq!(field_name("async"), "r#async");
+        assert_eq!(field_name("gen"), "r#gen");
+        assert_eq!(field_name("self"), "self_");
+        assert_eq!(field_name("\"Crate\""), "crate_");
+        assert_eq!(field_name("union"), "union");
+    }
+
+    #[test]
+    fn keyword_columns_keep_their_name_in_serde_and_row_mapping() {
+        let ddl = "CREATE TABLE type (id INT PRIMARY KEY, self TEXT NOT NULL, fn TEXT NOT NULL);";
+        let table = parse_create_tables(ddl).unwrap().remove(0);
+        let code = generate(&table, ddl);
+
+        assert!(code.contains("    #[serde(rename = \"self\")]\n    pub self_: String,"), "{}", code);
+        assert!(code.contains("self_: row.try_get(\"self\")?"), "{}", code);
+        assert!(code.contains("    pub r#fn: String,"), "{}", code);
+        assert!(!code.contains("#[serde(rename = \"fn\")]"), "{}", code);
+        assert!(code.contains("r#fn: row.try_get(\"fn\")?"), "{}", code);
+        assert!(code.contains("pub async fn insert_type("), "{}", code);
+    }
+
+    #[test]
+    fn extract_create_tables_skips_other_statements() {
+        let source = "-- notes (draft)
+            CREATE TABLE venue (doc_id TEXT PRIMARY KEY, note TEXT DEFAULT ')');
+            INSERT INTO venue VALUES ('a', 'b');
+            create table floor (id INT);
+            CREATE TABLE broken (id INT";
+        assert_eq!(
+            extract_create_tables(source),
+            vec![
+                "CREATE TABLE venue (doc_id TEXT PRIMARY KEY, note TEXT DEFAULT ')');".to_string(),
+                "create table floor (id INT);".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_create_tables_after_text_that_changes_length_when_uppercased() {
+        // The ligature "ﬁ" (3 bytes) uppercases to "FI" (2 bytes), offsets found in
+        // a to_uppercase copy would no longer point at CREATE TABLE in the source
+        let source = "-- ﬁrst floor\nCREATE TABLE room (id INT);";
+        assert_eq!(
+            extract_create_tables(source),
+            vec!["CREATE TABLE room (id INT);".to_string()]
+        );
+    }
+}
+
+[dependencies]
+argh = "0.1"
+sqlparser = "0.53"
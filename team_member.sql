@@ -0,0 +1,13 @@
+-- Employees in a team, the attendance export (attendance_export.rs) pages a team by employee_id
+CREATE TABLE IF NOT EXISTS team_member (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    created_on TIMESTAMP DEFAULT now(),
+    modified_on TIMESTAMP DEFAULT now(),
+    team_id BIGINT NOT NULL,
+    employee_id BIGINT NOT NULL REFERENCES employee(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    -- Also the index of the export keyset: team_id = $1 AND employee_id > $2 ORDER BY employee_id
+    UNIQUE (team_id, employee_id)
+);
+
+-- Teams of an employee, and the foreign key check when an employee is deleted
+CREATE INDEX IF NOT EXISTS team_member_employee ON team_member (employee_id);
@@ -0,0 +1,255 @@
+use chrono::NaiveDateTime;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use tokio_postgres::types::ToSql;
+
+/// Patch field with three states, same idea as in none1.rs:
+/// None = field missing, Some(None) = explicit null, Some(Some(v)) = value.
+/// Use with `#[serde(default, deserialize_with = "deserialize_nested_option")]`.
+pub fn deserialize_nested_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    // Only called when the field is present, so a missing field stays None via default
+    Ok(Some(Option::deserialize(deserializer)?))
+}
+
+/// Copy a patch value into a doc field that cannot be null
+pub fn merge_field<T: Clone>(target: &mut T, value: &Option<T>) {
+    if let Some(value) = value {
+        *target = value.clone();
+    }
+}
+
+/// Copy a three state patch value into a doc field that can be null
+pub fn merge_nullable_field<T: Clone>(target: &mut Option<T>, value: &Option<Option<T>>) {
+    if let Some(value) = value {
+        *target = value.clone();
+    }
+}
+
+/// Builds `UPDATE <table> SET ... WHERE ...` from the fields present in a patch.
+/// Column names are static so they never come from user input.
+pub struct UpdateBuilder {
+    table: &'static str,
+    set_clauses: Vec<String>,
+    params: Vec<Box<dyn ToSql + Sync + Send>>,
+    // modified_on the client last saw (If-Match), the update only applies if it is unchanged
+    expected_modified_on: Option<NaiveDateTime>,
+    // Set to record the change in doc_audit
+    audit: Option<AuditContext>,
+}
+
+impl UpdateBuilder {
+    pub fn new(table: &'static str) -> Self {
+        UpdateBuilder {
+            table,
+            set_clauses: Vec::new(),
+            params: Vec::new(),
+            expected_modified_on: None,
+            audit: None,
+        }
+    }
+
+    /// Always set the column
+    pub fn set_value<T: ToSql + Sync + Send + 'static>(&mut self, column: &'static str, value: T) -> &mut Self {
+        self.params.push(Box::new(value));
+        self.set_clauses
+            .push(format!("{} = ${}", column, self.params.len()));
+        self
+    }
+
+    /// Set the column only when the field was sent
+    pub fn set<T: ToSql + Sync + Send + Clone + 'static>(
+        &mut self,
+        column: &'static str,
+        value: &Option<T>,
+    ) -> &mut Self {
+        if let Some(value) = value {
+            self.set_value(column, value.clone());
+        }
+        self
+    }
+
+    /// Set the column when the field was sent, NULL when it was sent as null
+    pub fn set_nullable<T: ToSql + Sync + Send + Clone + 'static>(
+        &mut self,
+        column: &'static str,
+        value: &Option<Option<T>>,
+    ) -> &mut Self {
+        if let Some(value) = value {
+            self.set_value(column, value.clone());
+        }
+        self
+    }
+
+    /// Set the column to an SQL expression such as `current_timestamp`
+    pub fn set_raw(&mut self, column: &'static str, expression: &'static str) -> &mut Self {
+        self.set_clauses.push(format!("{} = {}", column, expression));
+        self
+    }
+
+    /// Only update the row if its modified_on still matches
+    pub fn if_unmodified(&mut self, modified_on: Option<NaiveDateTime>) -> &mut Self {
+        self.expected_modified_on = modified_on;
+        self
+    }
+
+    /// Record the change (before and after the update) in doc_audit
+    pub fn audit(&mut self, context: AuditContext) -> &mut Self {
+        self.audit = Some(context);
+        self
+    }
+
+    /// Whether any column will be written
+    pub fn has_changes(&self) -> bool {
+        !self.set_clauses.is_empty()
+    }
+
+    /// Run the update and return the number of affected rows.
+    /// Nothing is sent to the database when no column was set.
+    pub async fn execute(
+        &self,
+        db: &DBConnection<'_>,
+        filters: &[(&'static str, &(dyn ToSql + Sync))],
+    ) -> ApiResult<u64> {
+        if !self.has_changes() {
+            return Ok(0);
+        }
+        if filters.is_empty() {
+            return Err(ApiError::Error("Update without a where clause".to_string()));
+        }
+
+        let mut params: Vec<&(dyn ToSql + Sync)> = self
+            .params
+            .iter()
+            .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+            .collect();
+
+        let mut where_clauses = Vec::new();
+        for (column, value) in filters {
+            params.push(*value);
+            where_clauses.push(format!("{} = ${}", column, params.len()));
+        }
+        if let Some(modified_on) = &self.expected_modified_on {
+            params.push(modified_on);
+            where_clauses.push(format!("modified_on = ${}", params.len()));
+        }
+
+        // Locked so no other write lands between the snapshot and the update
+        let before = match &self.audit {
+            Some(_) => snapshot_row(db, self.table, filters, true).await?,
+            None => None,
+        };
+
+        let query = format!(
+            "UPDATE {} SET {} WHERE {}",
+            self.table,
+            self.set_clauses.join(", "),
+            where_clauses.join(" AND ")
+        );
+        let affected = db.execute(&query, &params).await?;
+
+        if let (Some(context), Some(before)) = (&self.audit, &before) {
+            if affected != 0 {
+                if let Some(after) = snapshot_row(db, self.table, filters, false).await? {
+                    record_audit(db, context, "update", before, &after).await?;
+                }
+            }
+        }
+        Ok(affected)
+    }
+
+    /// Like `execute`, but no affected row is turned into NotFound or, when the
+    /// row still exists, a Conflict carrying its current doc
+    pub async fn execute_versioned<T: DeserializeOwned>(
+        &self,
+        db: &DBConnection<'_>,
+        filters: &[(&'static str, &(dyn ToSql + Sync))],
+    ) -> Result<u64, UpdateError<T>> {
+        let affected = self.execute(db, filters).await?;
+        if affected != 0 || !self.has_changes() {
+            return Ok(affected);
+        }
+        Err(match current_version(db, self.table, filters).await? {
+            Some(conflict) => UpdateError::Conflict(conflict),
+            None => UpdateError::NotFound,
+        })
+    }
+}
+
+/// Stale write, the row was changed after the client read it
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateConflict<T> {
+    pub current: T,
+    pub modified_on: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub enum UpdateError<T> {
+    NotFound,
+    Conflict(UpdateConflict<T>),
+    Api(ApiError),
+}
+
+impl<T> From<ApiError> for UpdateError<T> {
+    fn from(e: ApiError) -> Self {
+        UpdateError::Api(e)
+    }
+}
+
+impl<T> From<tokio_postgres::Error> for UpdateError<T> {
+    fn from(e: tokio_postgres::Error) -> Self {
+        UpdateError::Api(e.into())
+    }
+}
+
+impl<T> From<serde_json::Error> for UpdateError<T> {
+    fn from(e: serde_json::Error) -> Self {
+        UpdateError::Api(e.into())
+    }
+}
+
+/// Compare the modified_on the client sent (If-Match) with the row before
+/// doing any work, returns the row's doc when they match
+pub async fn check_version<T: DeserializeOwned>(
+    db: &DBConnection<'_>,
+    table: &'static str,
+    filters: &[(&'static str, &(dyn ToSql + Sync))],
+    expected_modified_on: Option<NaiveDateTime>,
+) -> Result<T, UpdateError<T>> {
+    let current = current_version(db, table, filters)
+        .await?
+        .ok_or(UpdateError::NotFound)?;
+    match expected_modified_on {
+        Some(expected) if expected != current.modified_on => Err(UpdateError::Conflict(current)),
+        _ => Ok(current.current),
+    }
+}
+
+/// Current doc and modified_on of the row, None when it does not exist
+async fn current_version<T: DeserializeOwned>(
+    db: &DBConnection<'_>,
+    table: &'static str,
+    filters: &[(&'static str, &(dyn ToSql + Sync))],
+) -> ApiResult<Option<UpdateConflict<T>>> {
+    let where_clauses: Vec<String> = filters
+        .iter()
+        .enumerate()
+        .map(|(i, (column, _))| format!("{} = ${}", column, i + 1))
+        .collect();
+    let params: Vec<&(dyn ToSql + Sync)> = filters.iter().map(|(_, value)| *value).collect();
+
+    let query = format!(
+        "SELECT doc, modified_on FROM {} WHERE {}",
+        table,
+        where_clauses.join(" AND ")
+    );
+    match db.query_opt(&query, &params).await? {
+        Some(row) => Ok(Some(UpdateConflict {
+            current: row.get_as_struct("doc")?,
+            modified_on: row.get("modified_on"),
+        })),
+        None => Ok(None),
+    }
+}
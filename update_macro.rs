@@ -1,83 +1,92 @@
-use serde_json::Value;
+use chrono::NaiveDateTime;
+use serde::Deserialize;
 use tokio_postgres::types::ToSql;
 
-// Fetch the category doc
-let row = db
-    .query_opt(
-        "SELECT doc FROM category WHERE doc_id = $1 AND company_id = $2",
-        &[&category_id, &company_id],
-    )
-    .await?
-    .ok_or_else(|| ApiError::Error("Invalid category id".to_string()))?;
-
-let mut doc: CategoryRowDoc = row.get_as_struct("doc")?;
-
-// Update doc fields only if needed
-macro_rules! update_field {
-    ($opt:expr, $target:ident) => {
-        if let Some(value) = &$opt {
-            doc.$target = value.clone();
-        }
-    };
-}
-
-update_field!(input.icon, icon);
-update_field!(input.image, image);
-update_field!(input.info, info);
-update_field!(input.extral, extral);
-update_field!(input.extra2, extra2);
-update_field!(input.extra3, extra3);
-if let Some(list) = &input.sort_info {
-    doc.sort_info = list.clone();
-}
-if let Some(list) = &input.platforms {
-    doc.platforms = list.clone();
+// Missing fields are left as they are. Fields using deserialize_nested_option
+// can also be sent as null to clear them.
+#[derive(Debug, Deserialize)]
+pub struct CategoryUpdateInput {
+    pub icon: Option<String>,
+    pub image: Option<String>,
+    pub info: Option<String>,
+    pub extral: Option<String>,
+    pub extra2: Option<String>,
+    pub extra3: Option<String>,
+    pub sort_info: Option<Vec<String>>,
+    pub platforms: Option<Vec<String>>,
+    pub is_web_online: Option<bool>,
+    pub title: Option<String>,
+    pub enabled_platforms: Option<Vec<String>>,
+    pub printers: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_nested_option")]
+    pub time_slot_id: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_nested_option")]
+    pub parent_id: Option<Option<String>>,
+    pub user_tags: Option<Vec<String>>,
+    // modified_on from the client's last read (If-Match), a stale value is a conflict
+    pub modified_on: Option<NaiveDateTime>,
 }
-if let Some(val) = input.is_web_online {
-    doc.is_web_online = val;
-}
-
-// Start building SET clause
-let mut set_clauses = vec!["modified_on = current_timestamp".to_string()];
-let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
 
-// Always update doc if anything changed
-let doc_value = serde_json::to_value(&doc)?;
-set_clauses.push(format!("doc = ${}", params.len() + 1));
-params.push(&doc_value);
+let filters: [(&'static str, &(dyn ToSql + Sync)); 2] =
+    [("doc_id", &category_id), ("company_id", &company_id)];
 
-// Optional fields (simple macro to reduce repetition)
-macro_rules! opt_param {
-    ($field:expr, $name:literal) => {
-        if let Some(val) = &$field {
-            set_clauses.push(format!("{} = ${}", $name, params.len() + 1));
-            params.push(val);
-        }
-    };
-}
+// Fetch the category doc, failing early if it already changed since the client read it
+let mut doc: CategoryRowDoc =
+    check_version(db, "category", &filters, input.modified_on).await?;
 
-opt_param!(input.title, "title");
-opt_param!(input.enabled_platforms, "enabled_platforms");
-opt_param!(input.printers, "printers");
-opt_param!(input.time_slot_id, "time_slot_id");
-opt_param!(input.parent_id, "parent_id");
-opt_param!(input.user_tags, "user_tags");
+// Update doc fields only if sent
+merge_field(&mut doc.icon, &input.icon);
+merge_field(&mut doc.image, &input.image);
+merge_field(&mut doc.info, &input.info);
+merge_field(&mut doc.extral, &input.extral);
+merge_field(&mut doc.extra2, &input.extra2);
+merge_field(&mut doc.extra3, &input.extra3);
+merge_field(&mut doc.sort_info, &input.sort_info);
+merge_field(&mut doc.platforms, &input.platforms);
+merge_field(&mut doc.is_web_online, &input.is_web_online);
 
-// Add WHERE clause values
-let doc_id_idx = params.len() + 1;
-params.push(&category_id);
+let mut update = UpdateBuilder::new("category");
+update
+    // Also checked in the UPDATE itself, another editor may save in between
+    .if_unmodified(input.modified_on)
+    .audit(AuditContext {
+        org_id: company_id.to_string(),
+        actor_id: user_id.to_string(),
+        entity: "category",
+        entity_id: category_id.to_string(),
+    })
+    .set_raw("modified_on", "current_timestamp")
+    // Always update doc, it carries the merged fields above
+    .set_value("doc", serde_json::to_value(&doc)?)
+    .set("title", &input.title)
+    .set("enabled_platforms", &input.enabled_platforms)
+    .set("printers", &input.printers)
+    .set_nullable("time_slot_id", &input.time_slot_id)
+    .set("user_tags", &input.user_tags);
 
-let company_id_idx = params.len() + 1;
-params.push(&company_id);
+// Number of rows changed, a stale write comes back as UpdateError::Conflict
+// with the current doc so the client can merge and retry
+let affected = update
+    .execute_versioned::<CategoryRowDoc>(db, &filters)
+    .await?;
 
-// Final SQL
-let query = format!(
-    "UPDATE category SET {} WHERE doc_id = ${} AND company_id = ${}",
-    set_clauses.join(", "),
-    doc_id_idx,
-    company_id_idx
-);
-
-// Execute update
-let affected = db.execute(&query, &params).await?;
-Ok(affected != 0)
\ No newline at end of file
+// A new parent goes through move_category, which takes the tree lock and rejects
+// cycles and parents of another org. It runs after the versioned update since it
+// bumps modified_on too. Sending the current parent again is not a move.
+if let Some(parent_id) = &input.parent_id {
+    let current_parent: Option<String> = db
+        .query_one(
+            "SELECT parent_id FROM category WHERE doc_id = $1 AND company_id = $2",
+            &[&category_id, &company_id],
+        )
+        .await?
+        .get("parent_id");
+    if current_parent != *parent_id {
+        let mv = CategoryMoveInput {
+            parent_id: parent_id.clone(),
+            position: None,
+        };
+        move_category(db, &company_id.to_string(), &category_id.to_string(), &mv).await?;
+    }
+}
+Ok(affected)
@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_postgres::{Client, NoTls};
+
+type AppState = Arc<Client>;
+
+fn internal_error(e: tokio_postgres::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkLogEntry {
+    client_id: String,
+    logged_at: DateTime<Utc>,
+    source: String,
+    note: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkLogSyncInput {
+    employee_id: i64,
+    entries: Vec<WorkLogEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkLogSyncOutput {
+    // Every client_id the server now has, including ones sent before
+    synced: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimelineQuery {
+    employee_id: i64,
+    date: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+struct TimelineItem {
+    time: DateTime<Utc>,
+    hour: i32,
+    // "punch" for status log rows, "work-log" for notes
+    kind: String,
+    title: String,
+}
+
+/// Store a batch of entries from a device. Safe to retry, entries already
+/// stored are matched on client_id and skipped.
+async fn sync_work_logs(
+    State(client): State<AppState>,
+    Json(input): Json<WorkLogSyncInput>,
+) -> Result<Json<WorkLogSyncOutput>, (StatusCode, String)> {
+    let mut synced = vec![];
+    for entry in input.entries.iter() {
+        if !matches!(entry.source.as_str(), "hourly" | "habit") {
+            return Err((StatusCode::BAD_REQUEST, "Invalid source".to_string()));
+        }
+        if entry.client_id.is_empty() || entry.client_id.len() > 64 {
+            return Err((StatusCode::BAD_REQUEST, "Invalid client id".to_string()));
+        }
+
+        client
+            .execute(
+                "INSERT INTO work_log (employee_id, client_id, logged_at, log_date, source, note)
+                VALUES ($1, $2, $3, DATE($3), $4, $5)
+                ON CONFLICT (employee_id, client_id) DO NOTHING",
+                &[
+                    &input.employee_id,
+                    &entry.client_id,
+                    &entry.logged_at,
+                    &entry.source,
+                    &entry.note,
+                ],
+            )
+            .await
+            .map_err(internal_error)?;
+        synced.push(entry.client_id.clone());
+    }
+
+    Ok(Json(WorkLogSyncOutput { synced }))
+}
+
+/// Punches and work notes for one attendance day, in time order
+async fn get_timeline(
+    State(client): State<AppState>,
+    Query(input): Query<TimelineQuery>,
+) -> Result<Json<Vec<TimelineItem>>, (StatusCode, String)> {
+    let rows = client
+        .query(
+            "SELECT time, EXTRACT(HOUR FROM time)::INT AS hour, kind, title FROM (
+                SELECT created_on AS time, 'punch' AS kind, status AS title
+                FROM employee_status_log
+                WHERE employee_id = $1 AND DATE(created_on) = $2
+
+                UNION ALL
+
+                SELECT logged_at AS time, 'work-log' AS kind, note AS title
+                FROM work_log
+                WHERE employee_id = $1 AND log_date = $2
+            ) timeline
+            ORDER BY time",
+            &[&input.employee_id, &input.date],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(
+        rows.iter()
+            .map(|row| TimelineItem {
+                time: row.get("time"),
+                hour: row.get("hour"),
+                kind: row.get("kind"),
+                title: row.get("title"),
+            })
+            .collect(),
+    ))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), tokio_postgres::Error> {
+    let (client, connection) = tokio_postgres::connect(
+        "host=localhost user=postgres dbname=postgres password=password",
+        NoTls,
+    )
+    .await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+
+    let app = Router::new()
+        .route("/attendance/work-logs", post(sync_work_logs))
+        .route("/attendance/timeline", get(get_timeline))
+        .with_state(Arc::new(client));
+
+    println!("🚀 Server running at http://127.0.0.1:3000/attendance/timeline");
+    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+
+    Ok(())
+}
+
+
+[dependencies]
+axum = "0.6"
+tokio = { version = "1", features = ["full"] }
+tokio-postgres = { version = "0.7", features = ["with-chrono-0_4"] }
+chrono = { version = "0.4", features = ["serde"] }
+serde = { version = "1", features = ["derive"] }
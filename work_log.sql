@@ -0,0 +1,16 @@
+-- Hourly notes from the desktop loggers (hourly.rs, habit.rs), stored against the attendance day
+CREATE TABLE IF NOT EXISTS work_log (
+    id BIGSERIAL PRIMARY KEY NOT NULL,
+    created_on TIMESTAMP DEFAULT now(),
+    modified_on TIMESTAMP DEFAULT now(),
+    employee_id BIGINT NOT NULL REFERENCES employee(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    -- Generated on the device so retried syncs do not create duplicates
+    client_id VARCHAR(64) NOT NULL,
+    logged_at TIMESTAMPTZ NOT NULL,
+    log_date DATE NOT NULL,
+    source VARCHAR(16) NOT NULL,
+    note TEXT NOT NULL,
+    UNIQUE (employee_id, client_id)
+);
+
+CREATE INDEX IF NOT EXISTS work_log_employee_date ON work_log (employee_id, log_date);
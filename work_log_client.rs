@@ -0,0 +1,85 @@
+// Sync of the desktop loggers (hourly.rs, habit.rs) with the work log
+// endpoint of work_log.rs, included into each of them with #[path]
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Deserialize, Debug)]
+struct SyncResponse {
+    synced: Vec<String>,
+}
+
+/// Employee the entries are synced for. Without it every entry would be
+/// rejected by the backend, so syncing is not started at all.
+pub fn employee_id(label: &str) -> Option<i64> {
+    match std::env::var("EMPLOYEE_ID").map(|id| id.trim().parse::<i64>()) {
+        Ok(Ok(id)) if id > 0 => Some(id),
+        Ok(_) => {
+            eprintln!("EMPLOYEE_ID must be a positive number, {} sync is disabled", label);
+            None
+        }
+        Err(_) => {
+            eprintln!("EMPLOYEE_ID is not set, {} sync is disabled", label);
+            None
+        }
+    }
+}
+
+/// Post entries to the backend, returns the client ids it has stored
+pub async fn post_work_logs<T: Serialize>(
+    employee_id: i64,
+    entries: &[T],
+) -> Result<Vec<String>, reqwest::Error> {
+    let url = std::env::var("WORK_LOG_URL")
+        .unwrap_or_else(|_| "http://127.0.0.1:3000/attendance/work-logs".to_string());
+    let response: SyncResponse = reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({ "employee_id": employee_id, "entries": entries }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response.synced)
+}
+
+/// Entries kept on disk until they are synced. A file that does not parse is
+/// moved aside rather than read as empty, the next write would replace it.
+pub fn read_json_file<T: DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    match serde_json::from_str(&data) {
+        Ok(entries) => Ok(entries),
+        Err(e) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let backup = path.with_extension(format!("json.bad-{}", now));
+            fs::rename(path, &backup)?;
+            eprintln!(
+                "{} is not valid JSON ({}), moved to {}",
+                path.display(),
+                e,
+                backup.display()
+            );
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// Write through a temp file and rename it into place, a crash mid-write
+/// leaves the previous file instead of a truncated one
+pub fn write_json_file<T: Serialize>(path: &Path, entries: &[T]) -> io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(&serde_json::to_vec_pretty(entries)?)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}